@@ -0,0 +1,113 @@
+use super::component_storage::{ComponentsStorage, Storage};
+use super::hashing::EcsHashSet;
+use super::id_types::EntityId;
+use super::world::World;
+
+/// `C`'s storage resolved once, from [`World::component_accessor`], so lookups through it
+/// skip hashing the type id and downcasting that every `get_entity_component` call does.
+///
+/// Like iteration over the world, [`ComponentAccessor::iter`] and [`ComponentAccessor::len`]
+/// leave out entities hidden with [`World::set_active`], looking them up by id still works.
+pub struct ComponentAccessor<'w, C: 'static> {
+    component_storage: &'w ComponentsStorage<C>,
+    inactive_set: &'w EcsHashSet<EntityId>,
+    /// How many of the components belong to active entities, fixed while the world is borrowed.
+    len: usize,
+}
+
+impl<C: 'static> Clone for ComponentAccessor<'_, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C: 'static> Copy for ComponentAccessor<'_, C> {}
+
+impl World {
+    /// `None` if `C` was never registered.
+    pub fn component_accessor<C: 'static>(&self) -> Option<ComponentAccessor<'_, C>> {
+        let component_storage = self.get_component_storage::<C>()?;
+        let inactive_components = self
+            .inactive_set
+            .iter()
+            .filter(|&&entity_id| component_storage.contains(entity_id))
+            .count();
+
+        Some(ComponentAccessor {
+            component_storage,
+            inactive_set: &self.inactive_set,
+            len: component_storage.len() - inactive_components,
+        })
+    }
+}
+
+impl<'w, C: 'static> ComponentAccessor<'w, C> {
+    /// Despawned entities have no components, so there's no separate validity check.
+    pub fn get(&self, entity_id: EntityId) -> Option<&'w C> {
+        self.component_storage.get(entity_id)
+    }
+
+    pub fn contains(&self, entity_id: EntityId) -> bool {
+        self.component_storage.contains(entity_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// In storage order. It knows its exact length and can be walked from the back, say for
+    /// back-to-front rendering.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (EntityId, &'w C)> + ExactSizeIterator + 'w {
+        ActiveIter {
+            components: self.component_storage.components.iter(),
+            inactive_set: self.inactive_set,
+            remaining: self.len,
+        }
+    }
+}
+
+/// Skips inactive entities while still knowing its exact length, which a plain `filter`
+/// would lose.
+struct ActiveIter<'w, I> {
+    components: I,
+    inactive_set: &'w EcsHashSet<EntityId>,
+    remaining: usize,
+}
+
+impl<'w, C: 'w, I: Iterator<Item = (EntityId, &'w C)>> Iterator for ActiveIter<'w, I> {
+    type Item = (EntityId, &'w C);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let inactive_set = self.inactive_set;
+        let item = self
+            .components
+            .find(|(entity_id, _)| !inactive_set.contains(entity_id))?;
+        self.remaining -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'w, C: 'w, I: DoubleEndedIterator<Item = (EntityId, &'w C)>> DoubleEndedIterator
+    for ActiveIter<'w, I>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let inactive_set = self.inactive_set;
+        let item = self
+            .components
+            .rfind(|(entity_id, _)| !inactive_set.contains(entity_id))?;
+        self.remaining -= 1;
+        Some(item)
+    }
+}
+
+impl<'w, C: 'w, I: Iterator<Item = (EntityId, &'w C)>> ExactSizeIterator for ActiveIter<'w, I> {}
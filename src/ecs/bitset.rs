@@ -0,0 +1,80 @@
+use super::id_types::EntityId;
+
+/// A bit per entity index, set if the entity is in the storage. Each index also keeps a `u32`
+/// generation, so that's about 4 bytes per index up to the highest one inserted.
+#[derive(Default, Clone)]
+pub(super) struct EntityBitset {
+    words: Vec<u64>,
+    /// The generation of the entity behind every set bit.
+    generations: Vec<u32>,
+}
+
+impl EntityBitset {
+    pub(super) fn insert(&mut self, entity_id: EntityId) {
+        let (word, bit) = (entity_id.index / 64, entity_id.index % 64);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+            self.generations.resize(self.words.len() * 64, 0);
+        }
+        self.words[word] |= 1 << bit;
+        self.generations[entity_id.index] = entity_id.generation;
+    }
+
+    pub(super) fn remove(&mut self, entity_id: EntityId) {
+        let (word, bit) = (entity_id.index / 64, entity_id.index % 64);
+        if let Some(word) = self.words.get_mut(word) {
+            *word &= !(1 << bit);
+        }
+    }
+
+    /// Set under the same generation.
+    pub(super) fn contains(&self, entity_id: EntityId) -> bool {
+        self.entity_at(entity_id.index) == Some(entity_id)
+    }
+
+    /// The entity whose bit is set at `index`, if it is.
+    pub(super) fn entity_at(&self, index: usize) -> Option<EntityId> {
+        let word = self.words.get(index / 64)?;
+        (word & (1 << (index % 64)) != 0)
+            .then(|| EntityId::from_raw_parts(index, self.generations[index]))
+    }
+
+    /// One past the highest index a bit could be set at.
+    pub(super) fn index_count(&self) -> usize {
+        self.words.len() * 64
+    }
+
+    pub(super) fn memory_usage(&self) -> usize {
+        self.words.capacity() * std::mem::size_of::<u64>()
+            + self.generations.capacity() * std::mem::size_of::<u32>()
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.words.clear();
+        self.generations.clear();
+    }
+
+    /// Entities set in both under the same generation, in ascending index order.
+    pub(super) fn intersection<'a>(
+        &'a self,
+        other: &'a EntityBitset,
+    ) -> impl Iterator<Item = EntityId> + 'a {
+        self.words
+            .iter()
+            .zip(&other.words)
+            .enumerate()
+            .flat_map(|(word_index, (a, b))| {
+                let mut word = a & b;
+                std::iter::from_fn(move || {
+                    if word == 0 {
+                        return None;
+                    }
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(word_index * 64 + bit)
+                })
+            })
+            .filter(|&index| self.generations[index] == other.generations[index])
+            .map(|index| EntityId::from_raw_parts(index, self.generations[index]))
+    }
+}
@@ -0,0 +1,76 @@
+use super::error::Error;
+use super::id_types::EntityId;
+use super::world::World;
+
+/// A group of components added to an entity together, implemented for tuples of up to
+/// eight components, e.g. `(Position, Velocity)` or `(Health,)`.
+pub trait Bundle {
+    /// Stops at the first component that fails to be added.
+    fn add_to(self, world: &mut World, entity_id: EntityId) -> Result<(), Error>;
+}
+
+macro_rules! impl_bundle {
+    ($($component:ident),+) => {
+        impl<$($component: 'static),+> Bundle for ($($component,)+) {
+            #[allow(non_snake_case)]
+            fn add_to(self, world: &mut World, entity_id: EntityId) -> Result<(), Error> {
+                let ($($component,)+) = self;
+                $(world.add_entity_component(entity_id, $component)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+
+impl World {
+    /// Creates an entity with all of the bundle's components.
+    /// Panics if one of them can't be added, e.g. when a type appears twice in the bundle.
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityId {
+        self.try_spawn(bundle)
+            .unwrap_or_else(|error| panic!("Couldn't spawn the bundle: {}", error))
+    }
+
+    /// Like [`World::spawn`] but on error the half built entity is despawned again and the
+    /// error returned.
+    pub fn try_spawn<B: Bundle>(&mut self, bundle: B) -> Result<EntityId, Error> {
+        let entity_id = self.try_create_entity()?;
+        if let Err(error) = bundle.add_to(self, entity_id) {
+            self.despawn_entity(entity_id)
+                .expect("The entity was just created");
+            return Err(error);
+        }
+        Ok(entity_id)
+    }
+
+    /// Spawns an entity per bundle, returning the ids in the same order.
+    /// Panics like [`World::spawn`].
+    pub fn extend<B: Bundle, I: IntoIterator<Item = B>>(&mut self, bundles: I) -> Vec<EntityId> {
+        let bundles = bundles.into_iter();
+        let (lower_bound, _) = bundles.size_hint();
+        self.entity_validity_set.reserve(lower_bound);
+        self.live_indices.reserve(lower_bound);
+
+        let mut entity_ids = Vec::with_capacity(lower_bound);
+        entity_ids.extend(bundles.map(|bundle| self.spawn(bundle)));
+        entity_ids
+    }
+
+    /// Spawns `count` entities with the bundles `generate` makes from their index, for grids
+    /// and other patterns. Panics like [`World::spawn`].
+    pub fn spawn_batch<B: Bundle, F: FnMut(usize) -> B>(
+        &mut self,
+        count: usize,
+        generate: F,
+    ) -> Vec<EntityId> {
+        self.extend((0..count).map(generate))
+    }
+}
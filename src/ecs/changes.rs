@@ -0,0 +1,94 @@
+use std::ops::{Deref, DerefMut};
+
+use super::component_storage::Storage;
+use super::error::Error;
+use super::id_types::EntityId;
+use super::world::World;
+
+/// What happened to an entity's component, as recorded by [`World::track_changes`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    /// A mutable reference was handed out, the value itself isn't compared. Through [`Mut`]
+    /// it's only once the component is actually written.
+    Modified,
+    Removed,
+}
+
+/// A storage's log of changes, in the order they happened.
+pub(super) type ChangeLog = Vec<(EntityId, ChangeKind)>;
+
+/// A mutable component that records [`ChangeKind::Modified`] on its first [`DerefMut`] rather
+/// than when it's handed out, so reading through it doesn't count as a change.
+/// From [`World::get_entity_component_tracked_mut`].
+pub struct Mut<'w, C> {
+    entity_id: EntityId,
+    component: &'w mut C,
+    changes: &'w mut Option<ChangeLog>,
+    modified: bool,
+}
+
+impl<C> Deref for Mut<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.component
+    }
+}
+
+impl<C> DerefMut for Mut<'_, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        if !self.modified
+            && let Some(changes) = self.changes
+        {
+            changes.push((self.entity_id, ChangeKind::Modified));
+        }
+        self.modified = true;
+        self.component
+    }
+}
+
+impl World {
+    /// Makes `C`'s storage log every add, mutable access and removal until
+    /// [`World::drain_changes`] takes the log. Nothing is recorded before this is called.
+    pub fn track_changes<C: 'static>(&mut self) {
+        self.ensure_component_registered::<C>();
+        let component_storage = self
+            .get_component_storage_mut::<C>()
+            .expect("Was just registered");
+        component_storage.changes.get_or_insert_with(Vec::new);
+    }
+
+    /// The changes to `C` since the last drain, in the order they happened.
+    /// An entity shows up once per change, so one that was added and removed shows up twice.
+    pub fn drain_changes<C: 'static>(&mut self) -> Vec<(EntityId, ChangeKind)> {
+        self.get_component_storage_mut::<C>()
+            .and_then(|component_storage| component_storage.changes.as_mut())
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// [`World::get_entity_component_mut`] for precise change tracking, see [`Mut`].
+    pub fn get_entity_component_tracked_mut<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<Mut<'_, C>, Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        let component_storage = self
+            .get_component_storage_mut::<C>()
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
+        let (component, changes) = component_storage.get_mut_with_changes(entity_id).ok_or(
+            Error::InvalidEntityComponent(std::any::type_name::<C>(), entity_id),
+        )?;
+
+        Ok(Mut {
+            entity_id,
+            component,
+            changes,
+            modified: false,
+        })
+    }
+}
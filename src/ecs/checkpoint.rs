@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+
+use super::snapshot::WorldSnapshot;
+use super::world::World;
+
+/// The last few [`WorldSnapshot`]s of a world, for rolling back a couple of frames in
+/// lockstep netcode. Once full, every new checkpoint overwrites the oldest one.
+pub struct CheckpointRing {
+    checkpoints: VecDeque<WorldSnapshot>,
+    capacity: usize,
+}
+
+impl CheckpointRing {
+    /// Panics if `capacity` is 0, such a ring couldn't roll back at all.
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "A checkpoint ring needs room for at least one checkpoint"
+        );
+        Self {
+            checkpoints: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Snapshots `world` as the newest checkpoint, see [`World::snapshot`] for what's captured.
+    pub fn push_checkpoint(&mut self, world: &World) {
+        if self.checkpoints.len() == self.capacity {
+            self.checkpoints.pop_front();
+        }
+        self.checkpoints.push_back(world.snapshot());
+    }
+
+    /// Restores `world` to the checkpoint `frames_ago` pushes back, 0 being the newest.
+    /// The checkpoints after it are dropped, they're from a future that's being resimulated.
+    ///
+    /// Returns `false` and leaves `world` alone if the ring doesn't go back that far.
+    pub fn rollback_to(&mut self, world: &mut World, frames_ago: usize) -> bool {
+        let Some(index) = self.checkpoints.len().checked_sub(frames_ago + 1) else {
+            return false;
+        };
+
+        self.checkpoints.truncate(index + 1);
+        world.restore(&self.checkpoints[index]);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
@@ -1,23 +1,1127 @@
-use std::collections::HashMap;
+use std::any::Any;
+use std::collections::{HashMap, TryReserveError};
 
+use super::bitset::EntityBitset;
+use super::changes::{ChangeKind, ChangeLog};
+use super::entity_index::EntityIndex;
+use super::hashing::EcsHashSet;
 use super::id_types::EntityId;
+use super::tag_set::TagSet;
 
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
 pub enum ComponentStorageType {
+    #[default]
     SparseSet,
     Archetypes,
+    /// A sparse set that keeps ids and components in separate, index-aligned vecs, so the
+    /// components are one contiguous `&[C]`. Enabled per type with `World::enable_soa`.
+    SoA,
+}
+
+/// The typed operations the world and the queries use on a storage, so they don't depend on
+/// how a backend keeps its components. [`ComponentsStorage`] is the sparse set one, an
+/// archetype table would be the second implementor. It's not exported, so nothing outside
+/// `ecs` can implement it.
+pub(super) trait Storage<C: 'static> {
+    fn get(&self, entity_id: EntityId) -> Option<&C>;
+
+    /// Records a [`ChangeKind::Modified`] if changes are tracked.
+    fn get_mut(&mut self, entity_id: EntityId) -> Option<&mut C>;
+
+    /// The component along with the change log, which is `None` unless changes are tracked.
+    /// Nothing is recorded, it's for [`super::changes::Mut`] which does it on the first write.
+    fn get_mut_with_changes(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Option<(&mut C, &mut Option<ChangeLog>)>;
+
+    fn contains(&self, entity_id: EntityId) -> bool;
+
+    /// Stores the component, the entity must not already have one.
+    fn insert(&mut self, entity_id: EntityId, component_data: C);
+
+    fn remove(&mut self, entity_id: EntityId) -> Option<C>;
+
+    fn len(&self) -> usize;
+
+    /// In storage order.
+    fn iter(&self) -> impl Iterator<Item = (EntityId, &C)>;
+
+    /// In storage order. Unlike [`Self::get_mut`] nothing is recorded, the caller records
+    /// changes for what it hands out.
+    fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut C)>;
+
+    /// Lends out the components of different entities mutably at the same time, for joins
+    /// and swaps. Nothing is recorded either.
+    fn slots_mut(&mut self) -> SlotsMut<'_, C>;
+
+    /// The stored entities as a bitset, if the backend keeps one, for cheap joins.
+    fn bitset(&self) -> Option<&EntityBitset> {
+        None
+    }
+}
+
+/// What the world can do with a storage without knowing its component type.
+pub(super) trait AnyComponentsStorage: Any {
+    fn component_type_name(&self) -> &'static str;
+
+    fn contains_entity(&self, entity_id: EntityId) -> bool;
+
+    /// Rough number of bytes allocated by the storage.
+    fn memory_usage(&self) -> usize;
+
+    /// Describes the first broken invariant between the map, the vec and the live entities.
+    fn check_consistency(&self, entity_validity_set: &EcsHashSet<EntityId>) -> Result<(), String>;
+
+    /// Returns `true` if the entity had a component in this storage.
+    fn remove_entity(&mut self, entity_id: EntityId) -> bool;
+
+    /// Removes the components of every entity for which `keep` returns `false`.
+    fn retain_entities(&mut self, keep: &mut dyn FnMut(EntityId) -> bool);
+
+    /// Calls `f` with every entity in the storage, in storage order.
+    fn for_each_entity(&self, f: &mut dyn FnMut(EntityId));
+
+    /// Moves every component to the id its entity maps to, the order stays the same.
+    /// Components and logged changes of entities that aren't in `id_map` are dropped.
+    fn remap_entities(&mut self, id_map: &HashMap<EntityId, EntityId>);
+}
+
+/// Deep copies a storage, only possible when the component type is known to be `Clone`,
+/// so it's captured at registration as a `clone_storage::<C>` shim.
+pub(super) type StorageCloneFn = fn(&dyn AnyComponentsStorage) -> Box<dyn AnyComponentsStorage>;
+
+pub(super) fn clone_storage<C: Clone + 'static>(
+    component_storage: &dyn AnyComponentsStorage,
+) -> Box<dyn AnyComponentsStorage> {
+    let component_storage = (component_storage as &dyn Any)
+        .downcast_ref::<ComponentsStorage<C>>()
+        .expect("The shim is registered under the component's own id");
+
+    Box::new(ComponentsStorage {
+        components: component_storage.components.clone(),
+        entity_component_map: component_storage.entity_component_map.clone(),
+        bitset: component_storage.bitset.clone(),
+        changes: component_storage.changes.clone(),
+        // The pooled values are spare, the clone only needs to know pooling is on.
+        pool: component_storage.pool.as_ref().map(|_| Vec::new()),
+    })
+}
+
+/// The dense part of a storage, both layouts are kept packed and in the same order.
+#[derive(Clone)]
+pub(super) enum ComponentsLayout<C> {
+    Interleaved(Vec<(EntityId, C)>),
+    SoA {
+        entity_ids: Vec<EntityId>,
+        components: Vec<C>,
+    },
+    /// Either of the above once a stable removal left an empty slot, a tombstone, so no
+    /// other component had to move. Every removal leaves one until it's packed again with
+    /// `World::compact`, which goes back to SoA if `soa` is set.
+    Tombstoned {
+        slots: Vec<(EntityId, Option<C>)>,
+        live: usize,
+        soa: bool,
+    },
+    /// Zero-sized components, chosen for them when the storage is made. The slot of a tag is
+    /// its entity's index, so there's no entity map and the storage order is index order.
+    /// Stable removal is the same as any other, there's nothing to move.
+    Tags(TagSet<C>),
+}
+
+impl<C> ComponentsLayout<C> {
+    /// Stored components, tombstones don't count.
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Self::Interleaved(component_vec) => component_vec.len(),
+            Self::SoA { entity_ids, .. } => entity_ids.len(),
+            Self::Tombstoned { live, .. } => *live,
+            Self::Tags(tags) => tags.len(),
+        }
+    }
+
+    /// Like [`Self::len`] but tombstones count, indices go up to this.
+    pub(super) fn slot_count(&self) -> usize {
+        match self {
+            Self::Tombstoned { slots, .. } => slots.len(),
+            Self::Tags(tags) => tags.slot_count(),
+            _ => self.len(),
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(super) fn entity_id_at(&self, index: usize) -> Option<EntityId> {
+        match self {
+            Self::Interleaved(component_vec) => {
+                component_vec.get(index).map(|(entity_id, _)| *entity_id)
+            }
+            Self::SoA { entity_ids, .. } => entity_ids.get(index).copied(),
+            Self::Tombstoned { slots, .. } => match slots.get(index)? {
+                (entity_id, Some(_)) => Some(*entity_id),
+                (_, None) => None,
+            },
+            Self::Tags(tags) => tags.entity_at(index),
+        }
+    }
+
+    fn component_at(&self, index: usize) -> &C {
+        match self {
+            Self::Interleaved(component_vec) => &component_vec[index].1,
+            Self::SoA { components, .. } => &components[index],
+            Self::Tombstoned { slots, .. } => slots[index]
+                .1
+                .as_ref()
+                .expect("Mapped slots aren't tombstones"),
+            Self::Tags(_) => TagSet::tag(),
+        }
+    }
+
+    fn component_at_mut(&mut self, index: usize) -> &mut C {
+        match self {
+            Self::Interleaved(component_vec) => &mut component_vec[index].1,
+            Self::SoA { components, .. } => &mut components[index],
+            Self::Tombstoned { slots, .. } => slots[index]
+                .1
+                .as_mut()
+                .expect("Mapped slots aren't tombstones"),
+            Self::Tags(_) => TagSet::tag_mut(),
+        }
+    }
+
+    /// Appends the component, returning the index it got.
+    fn push(&mut self, entity_id: EntityId, component_data: C) -> usize {
+        let index = self.slot_count();
+        match self {
+            Self::Interleaved(component_vec) => component_vec.push((entity_id, component_data)),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => {
+                entity_ids.push(entity_id);
+                components.push(component_data);
+            }
+            Self::Tombstoned { slots, live, .. } => {
+                slots.push((entity_id, Some(component_data)));
+                *live += 1;
+            }
+            Self::Tags(tags) => {
+                tags.insert(entity_id, component_data);
+                return entity_id.index;
+            }
+        }
+        index
+    }
+
+    fn remap_entity_ids(&mut self, mut remap: impl FnMut(EntityId) -> EntityId) {
+        match self {
+            Self::Interleaved(component_vec) => {
+                for (entity_id, _) in component_vec {
+                    *entity_id = remap(*entity_id);
+                }
+            }
+            Self::SoA { entity_ids, .. } => {
+                for entity_id in entity_ids {
+                    *entity_id = remap(*entity_id);
+                }
+            }
+            Self::Tombstoned { slots, .. } => {
+                for (entity_id, _) in slots.iter_mut().filter(|(_, slot)| slot.is_some()) {
+                    *entity_id = remap(*entity_id);
+                }
+            }
+            Self::Tags(tags) => tags.remap_entity_ids(remap),
+        }
+    }
+
+    /// Takes the component at `index` out, moving the last one into its place. A tombstoned
+    /// layout leaves a tombstone instead, nothing moves.
+    fn swap_remove(&mut self, index: usize) -> C {
+        match self {
+            Self::Interleaved(component_vec) => component_vec.swap_remove(index).1,
+            Self::SoA {
+                entity_ids,
+                components,
+            } => {
+                entity_ids.swap_remove(index);
+                components.swap_remove(index)
+            }
+            Self::Tombstoned { .. } => self.tombstone(index),
+            Self::Tags(tags) => tags.remove(index),
+        }
+    }
+
+    /// Takes the component at `index` out, leaving a tombstone so no other index changes.
+    fn tombstone(&mut self, index: usize) -> C {
+        if let Self::Tags(tags) = self {
+            return tags.remove(index);
+        }
+        if !matches!(self, Self::Tombstoned { .. }) {
+            let soa = matches!(self, Self::SoA { .. });
+            let live = self.len();
+            let slots = std::mem::replace(self, Self::Interleaved(Vec::new()))
+                .into_pairs()
+                .map(|(entity_id, component)| (entity_id, Some(component)))
+                .collect();
+            *self = Self::Tombstoned { slots, live, soa };
+        }
+
+        let Self::Tombstoned { slots, live, .. } = self else {
+            unreachable!("Was just tombstoned");
+        };
+        let component = slots[index]
+            .1
+            .take()
+            .expect("Mapped slots aren't tombstones");
+        *live -= 1;
+        component
+    }
+
+    /// Drops the tombstones, back to the packed layout. The components keep their order but
+    /// the ones after a tombstone move down.
+    fn pack(&mut self) {
+        let Self::Tombstoned { soa, .. } = *self else {
+            return;
+        };
+        let packed = Self::Interleaved(
+            std::mem::replace(self, Self::Interleaved(Vec::new()))
+                .into_pairs()
+                .collect(),
+        );
+        *self = if soa { packed.into_soa() } else { packed };
+    }
+
+    /// Also drops the tombstones.
+    pub(super) fn clear(&mut self) {
+        match self {
+            Self::Interleaved(component_vec) => component_vec.clear(),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => {
+                entity_ids.clear();
+                components.clear();
+            }
+            Self::Tombstoned { soa, .. } => {
+                *self = if *soa {
+                    Self::SoA {
+                        entity_ids: Vec::new(),
+                        components: Vec::new(),
+                    }
+                } else {
+                    Self::Interleaved(Vec::new())
+                };
+            }
+            Self::Tags(tags) => tags.clear(),
+        }
+    }
+
+    pub(super) fn reserve(&mut self, additional: usize) {
+        match self {
+            Self::Interleaved(component_vec) => component_vec.reserve(additional),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => {
+                entity_ids.reserve(additional);
+                components.reserve(additional);
+            }
+            Self::Tombstoned { slots, .. } => slots.reserve(additional),
+            Self::Tags(_) => {}
+        }
+    }
+
+    fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        match self {
+            Self::Interleaved(component_vec) => component_vec.try_reserve(additional),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => {
+                entity_ids.try_reserve(additional)?;
+                components.try_reserve(additional)
+            }
+            Self::Tombstoned { slots, .. } => slots.try_reserve(additional),
+            Self::Tags(_) => Ok(()),
+        }
+    }
+
+    pub(super) fn shrink_to_fit(&mut self) {
+        match self {
+            Self::Interleaved(component_vec) => component_vec.shrink_to_fit(),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => {
+                entity_ids.shrink_to_fit();
+                components.shrink_to_fit();
+            }
+            Self::Tombstoned { slots, .. } => slots.shrink_to_fit(),
+            Self::Tags(_) => {}
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        match self {
+            Self::Interleaved(component_vec) => {
+                component_vec.capacity() * std::mem::size_of::<(EntityId, C)>()
+            }
+            Self::SoA {
+                entity_ids,
+                components,
+            } => {
+                entity_ids.capacity() * std::mem::size_of::<EntityId>()
+                    + components.capacity() * std::mem::size_of::<C>()
+            }
+            Self::Tombstoned { slots, .. } => {
+                slots.capacity() * std::mem::size_of::<(EntityId, Option<C>)>()
+            }
+            Self::Tags(tags) => tags.memory_usage(),
+        }
+    }
+
+    /// In storage order.
+    pub(super) fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (EntityId, &C)> + ExactSizeIterator {
+        match self {
+            Self::Interleaved(component_vec) => LayoutIter::Interleaved(
+                component_vec
+                    .iter()
+                    .map(|(entity_id, component)| (*entity_id, component)),
+            ),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => LayoutIter::SoA(entity_ids.iter().copied().zip(components)),
+            Self::Tombstoned { slots, live, .. } => LayoutIter::Tombstoned(LiveSlots {
+                slots: slots
+                    .iter()
+                    .map(|(entity_id, slot)| Some((*entity_id, slot.as_ref()?))),
+                remaining: *live,
+            }),
+            Self::Tags(tags) => LayoutIter::Tags(LiveSlots {
+                slots: (0..tags.slot_count())
+                    .map(|index| Some((tags.entity_at(index)?, TagSet::tag()))),
+                remaining: tags.len(),
+            }),
+        }
+    }
+
+    /// Lends out components by index for as long as the layout is borrowed, so a join can
+    /// hand out `&mut C`s of different entities while it goes.
+    fn slots_mut<'a>(&'a mut self, entity_component_map: &'a EntityIndex) -> SlotsMut<'a, C> {
+        let slot_count = self.slot_count();
+        let (slots, slot_lookup) = match self {
+            Self::Interleaved(component_vec) => (
+                SlotPtrs::Interleaved(component_vec.as_mut_ptr()),
+                SlotLookup::Map(entity_component_map),
+            ),
+            Self::SoA { components, .. } => (
+                SlotPtrs::SoA(components.as_mut_ptr()),
+                SlotLookup::Map(entity_component_map),
+            ),
+            Self::Tombstoned { slots, .. } => (
+                SlotPtrs::Tombstoned(slots.as_mut_ptr()),
+                SlotLookup::Map(entity_component_map),
+            ),
+            Self::Tags(tags) => (SlotPtrs::Tags, SlotLookup::Tags(tags)),
+        };
+        SlotsMut {
+            slots,
+            slot_count,
+            slot_lookup,
+            layout: std::marker::PhantomData,
+        }
+    }
+
+    /// Where the components of this layout are found, `entity_component_map` unless they're
+    /// tags.
+    pub(super) fn slot_lookup<'a>(
+        &'a self,
+        entity_component_map: &'a EntityIndex,
+    ) -> SlotLookup<'a, C> {
+        match self {
+            Self::Tags(tags) => SlotLookup::Tags(tags),
+            _ => SlotLookup::Map(entity_component_map),
+        }
+    }
+
+    pub(super) fn iter_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = (EntityId, &mut C)> + ExactSizeIterator {
+        match self {
+            Self::Interleaved(component_vec) => LayoutIter::Interleaved(
+                component_vec
+                    .iter_mut()
+                    .map(|(entity_id, component)| (*entity_id, component)),
+            ),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => LayoutIter::SoA(entity_ids.iter().copied().zip(components)),
+            Self::Tombstoned { slots, live, .. } => LayoutIter::Tombstoned(LiveSlots {
+                slots: slots
+                    .iter_mut()
+                    .map(|(entity_id, slot)| Some((*entity_id, slot.as_mut()?))),
+                remaining: *live,
+            }),
+            Self::Tags(tags) => LayoutIter::Tags(LiveSlots {
+                slots: (0..tags.slot_count())
+                    .map(|index| Some((tags.entity_at(index)?, TagSet::tag_mut()))),
+                remaining: tags.len(),
+            }),
+        }
+    }
+
+    pub(super) fn entity_ids(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = EntityId> + ExactSizeIterator {
+        self.iter().map(|(entity_id, _)| entity_id)
+    }
+
+    /// Leaves the tombstones behind, [`Self::clear`] drops them.
+    fn drain(&mut self) -> impl Iterator<Item = (EntityId, C)> {
+        let (interleaved, soa, tombstoned, tags) = match self {
+            Self::Interleaved(component_vec) => (Some(component_vec.drain(..)), None, None, None),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => (
+                None,
+                Some(entity_ids.drain(..).zip(components.drain(..))),
+                None,
+                None,
+            ),
+            Self::Tombstoned { slots, live, .. } => {
+                *live = 0;
+                (None, None, Some(slots.drain(..)), None)
+            }
+            Self::Tags(tags) => (None, None, None, Some(tags.take_all())),
+        };
+
+        interleaved
+            .into_iter()
+            .flatten()
+            .chain(soa.into_iter().flatten())
+            .chain(
+                tombstoned
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(entity_id, slot)| Some((entity_id, slot?))),
+            )
+            .chain(tags.into_iter().flatten())
+    }
+
+    pub(super) fn into_pairs(self) -> impl Iterator<Item = (EntityId, C)> {
+        let (interleaved, soa, tombstoned, tags) = match self {
+            Self::Interleaved(component_vec) => (Some(component_vec.into_iter()), None, None, None),
+            Self::SoA {
+                entity_ids,
+                components,
+            } => (
+                None,
+                Some(entity_ids.into_iter().zip(components)),
+                None,
+                None,
+            ),
+            Self::Tombstoned { slots, .. } => (None, None, Some(slots.into_iter()), None),
+            Self::Tags(mut tags) => (None, None, None, Some(tags.take_all())),
+        };
+
+        interleaved
+            .into_iter()
+            .flatten()
+            .chain(soa.into_iter().flatten())
+            .chain(
+                tombstoned
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(entity_id, slot)| Some((entity_id, slot?))),
+            )
+            .chain(tags.into_iter().flatten())
+    }
+
+    /// The same components in the other layout, in the same order.
+    fn into_soa(self) -> Self {
+        match self {
+            Self::Interleaved(component_vec) => {
+                let (entity_ids, components) = component_vec.into_iter().unzip();
+                Self::SoA {
+                    entity_ids,
+                    components,
+                }
+            }
+            soa @ Self::SoA { .. } => soa,
+            tags @ Self::Tags(_) => tags,
+            Self::Tombstoned { slots, live, .. } => Self::Tombstoned {
+                slots,
+                live,
+                soa: true,
+            },
+        }
+    }
+}
+
+enum SlotPtrs<C> {
+    Interleaved(*mut (EntityId, C)),
+    SoA(*mut C),
+    Tombstoned(*mut (EntityId, Option<C>)),
+    /// Tags have no memory to point at.
+    Tags,
+}
+
+/// Finds the slot of an entity's component, see [`ComponentsLayout::slot_lookup`].
+pub(super) enum SlotLookup<'a, C> {
+    Map(&'a EntityIndex),
+    Tags(&'a TagSet<C>),
+}
+
+impl<C> SlotLookup<'_, C> {
+    pub(super) fn get(&self, entity_id: EntityId) -> Option<usize> {
+        match self {
+            Self::Map(entity_component_map) => entity_component_map.get(&entity_id).copied(),
+            Self::Tags(tags) => tags.contains(entity_id).then_some(entity_id.index),
+        }
+    }
+}
+
+/// Components of a mutably borrowed storage, see [`Storage::slots_mut`].
+pub(super) struct SlotsMut<'a, C> {
+    slots: SlotPtrs<C>,
+    slot_count: usize,
+    slot_lookup: SlotLookup<'a, C>,
+    layout: std::marker::PhantomData<&'a mut ComponentsLayout<C>>,
+}
+
+impl<'a, C> SlotsMut<'a, C> {
+    /// The entity's component, `None` if it has none.
+    ///
+    /// # Safety
+    ///
+    /// No entity may be taken twice.
+    pub(super) unsafe fn take(&self, entity_id: EntityId) -> Option<&'a mut C> {
+        let index = self.slot_lookup.get(entity_id)?;
+        assert!(index < self.slot_count, "Slot {} is out of bounds", index);
+        // SAFETY: The index is in bounds of the buffer the pointer came from, which can't
+        // move while the layout is borrowed, and mapped slots aren't tombstones. Every entity
+        // maps to its own slot and the caller never takes one twice. Only the component is
+        // referenced, never the whole slot or buffer.
+        Some(unsafe {
+            match self.slots {
+                SlotPtrs::Interleaved(slots) => &mut (*slots.add(index)).1,
+                SlotPtrs::SoA(components) => &mut *components.add(index),
+                SlotPtrs::Tombstoned(slots) => (*slots.add(index))
+                    .1
+                    .as_mut()
+                    .expect("Mapped slots aren't tombstones"),
+                SlotPtrs::Tags => TagSet::tag_mut(),
+            }
+        })
+    }
+}
+
+/// The components of a tombstoned layout, skipping the tombstones while still knowing how
+/// many are left.
+struct LiveSlots<I> {
+    slots: I,
+    remaining: usize,
+}
+
+impl<T, I: Iterator<Item = Option<T>>> Iterator for LiveSlots<I> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let item = self.slots.by_ref().flatten().next()?;
+        self.remaining -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, I: DoubleEndedIterator<Item = Option<T>>> DoubleEndedIterator for LiveSlots<I> {
+    fn next_back(&mut self) -> Option<T> {
+        let item = self.slots.by_ref().rev().flatten().next()?;
+        self.remaining -= 1;
+        Some(item)
+    }
+}
+
+impl<T, I: Iterator<Item = Option<T>>> ExactSizeIterator for LiveSlots<I> {}
+
+/// Iterates any layout with one type, so the length and reverse iteration of the
+/// underlying iterators carry through.
+enum LayoutIter<I, S, L, G> {
+    Interleaved(I),
+    SoA(S),
+    Tombstoned(L),
+    Tags(G),
+}
+
+impl<T, I: Iterator<Item = T>, S: Iterator<Item = T>, L: Iterator<Item = T>, G: Iterator<Item = T>>
+    Iterator for LayoutIter<I, S, L, G>
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match self {
+            Self::Interleaved(iter) => iter.next(),
+            Self::SoA(iter) => iter.next(),
+            Self::Tombstoned(iter) => iter.next(),
+            Self::Tags(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Interleaved(iter) => iter.size_hint(),
+            Self::SoA(iter) => iter.size_hint(),
+            Self::Tombstoned(iter) => iter.size_hint(),
+            Self::Tags(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<
+    T,
+    I: DoubleEndedIterator<Item = T>,
+    S: DoubleEndedIterator<Item = T>,
+    L: DoubleEndedIterator<Item = T>,
+    G: DoubleEndedIterator<Item = T>,
+> DoubleEndedIterator for LayoutIter<I, S, L, G>
+{
+    fn next_back(&mut self) -> Option<T> {
+        match self {
+            Self::Interleaved(iter) => iter.next_back(),
+            Self::SoA(iter) => iter.next_back(),
+            Self::Tombstoned(iter) => iter.next_back(),
+            Self::Tags(iter) => iter.next_back(),
+        }
+    }
+}
+
+impl<
+    T,
+    I: ExactSizeIterator<Item = T>,
+    S: ExactSizeIterator<Item = T>,
+    L: ExactSizeIterator<Item = T>,
+    G: ExactSizeIterator<Item = T>,
+> ExactSizeIterator for LayoutIter<I, S, L, G>
+{
 }
 
 pub(super) struct ComponentsStorage<C: 'static> {
-    pub(super) component_vec: Vec<(EntityId, C)>,
+    pub(super) components: ComponentsLayout<C>,
     /// A map between entity IDs and their respective component index
-    pub(super) entity_component_map: HashMap<EntityId, usize>,
+    pub(super) entity_component_map: EntityIndex,
+    /// Only kept when enabled with `World::enable_bitset`.
+    pub(super) bitset: Option<EntityBitset>,
+    /// Only kept when enabled with `World::track_changes`.
+    pub(super) changes: Option<ChangeLog>,
+    /// Only kept when enabled with `World::register_pooled`.
+    pub(super) pool: Option<Vec<C>>,
+}
+
+/// Formats an entity's component with `Debug`, captured at registration like [`StorageCloneFn`].
+pub(super) type StorageDebugFn = fn(&dyn AnyComponentsStorage, EntityId) -> Option<String>;
+
+pub(super) fn debug_component<C: std::fmt::Debug + 'static>(
+    component_storage: &dyn AnyComponentsStorage,
+    entity_id: EntityId,
+) -> Option<String> {
+    (component_storage as &dyn Any)
+        .downcast_ref::<ComponentsStorage<C>>()?
+        .get(entity_id)
+        .map(|component| format!("{:?}", component))
 }
 
 impl<C> ComponentsStorage<C> {
     pub(super) fn new() -> Self {
+        Self::with_max_entities(None)
+    }
+
+    /// A storage with a dense index sized for `max_entities` if it's given, see
+    /// `World::with_dense_capacity`.
+    pub(super) fn with_max_entities(max_entities: Option<usize>) -> Self {
+        // Tags have no entity map, the dense one would be allocated for nothing.
+        let (components, max_entities) = if std::mem::size_of::<C>() == 0 {
+            (ComponentsLayout::Tags(TagSet::new()), None)
+        } else {
+            (ComponentsLayout::Interleaved(Vec::new()), max_entities)
+        };
+
         Self {
-            component_vec: Vec::new(),
-            entity_component_map: HashMap::new(),
+            components,
+            entity_component_map: EntityIndex::new(max_entities),
+            bitset: None,
+            changes: None,
+            pool: None,
+        }
+    }
+
+    /// Keeps a component the world would otherwise drop for reuse, if pooling is on.
+    fn recycle(&mut self, component_data: C) {
+        if let Some(pool) = &mut self.pool {
+            pool.push(component_data);
+        }
+    }
+
+    pub(super) fn storage_type(&self) -> ComponentStorageType {
+        match self.components {
+            ComponentsLayout::Interleaved(_)
+            | ComponentsLayout::Tombstoned { soa: false, .. }
+            | ComponentsLayout::Tags(_) => ComponentStorageType::SparseSet,
+            ComponentsLayout::SoA { .. } | ComponentsLayout::Tombstoned { soa: true, .. } => {
+                ComponentStorageType::SoA
+            }
+        }
+    }
+
+    /// Moves the components into the [`ComponentStorageType::SoA`] layout, indices don't change.
+    pub(super) fn enable_soa(&mut self) {
+        let components = std::mem::replace(
+            &mut self.components,
+            ComponentsLayout::Interleaved(Vec::new()),
+        );
+        self.components = components.into_soa();
+    }
+
+    pub(super) fn record_change(&mut self, entity_id: EntityId, change_kind: ChangeKind) {
+        if let Some(changes) = &mut self.changes {
+            changes.push((entity_id, change_kind));
+        }
+    }
+
+    /// [`Self::record_change`] for every stored entity.
+    pub(super) fn record_change_of_all(&mut self, change_kind: ChangeKind) {
+        if let Some(changes) = &mut self.changes {
+            changes.extend(
+                self.components
+                    .entity_ids()
+                    .map(|entity_id| (entity_id, change_kind)),
+            );
+        }
+    }
+
+    /// Tags are a bitset already, they don't get a second one.
+    pub(super) fn enable_bitset(&mut self) {
+        if matches!(self.components, ComponentsLayout::Tags(_)) {
+            return;
+        }
+        let mut bitset = EntityBitset::default();
+        for entity_id in self.components.entity_ids() {
+            bitset.insert(entity_id);
+        }
+        self.bitset = Some(bitset);
+    }
+
+    /// Empties the storage, the map right away and the vec as the drain is consumed or dropped.
+    pub(super) fn drain(&mut self) -> impl Iterator<Item = (EntityId, C)> + '_ {
+        self.record_change_of_all(ChangeKind::Removed);
+        self.entity_component_map.clear();
+        if let Some(bitset) = &mut self.bitset {
+            bitset.clear();
+        }
+        self.components.drain()
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.record_change_of_all(ChangeKind::Removed);
+        if let Some(pool) = &mut self.pool {
+            pool.extend(self.components.drain().map(|(_, component)| component));
+        }
+        self.components.clear();
+        self.entity_component_map.clear();
+        if let Some(bitset) = &mut self.bitset {
+            bitset.clear();
+        }
+    }
+
+    /// Room for `additional` more components without reallocating, failing instead of aborting
+    /// when there's no memory for it.
+    pub(super) fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.components.try_reserve(additional)?;
+        if matches!(self.components, ComponentsLayout::Tags(_)) {
+            return Ok(());
+        }
+        self.entity_component_map.try_reserve(additional)
+    }
+
+    pub(super) fn reserve(&mut self, additional: usize) {
+        self.components.reserve(additional);
+        if !matches!(self.components, ComponentsLayout::Tags(_)) {
+            self.entity_component_map.reserve(additional);
+        }
+    }
+
+    /// Forgets where the entity's component is, returning its slot.
+    fn unmap(&mut self, entity_id: EntityId) -> Option<usize> {
+        match &self.components {
+            ComponentsLayout::Tags(tags) => tags.contains(entity_id).then_some(entity_id.index),
+            _ => self.entity_component_map.remove(&entity_id),
+        }
+    }
+
+    fn slot_of(&self, entity_id: EntityId) -> Option<usize> {
+        self.components
+            .slot_lookup(&self.entity_component_map)
+            .get(entity_id)
+    }
+
+    /// Removes the entity's component leaving a tombstone, so every other component keeps
+    /// its index. The first one turns the layout into [`ComponentsLayout::Tombstoned`].
+    pub(super) fn remove_stable(&mut self, entity_id: EntityId) -> Option<C> {
+        let entity_component_index = self.unmap(entity_id)?;
+        let entity_component_data = self.components.tombstone(entity_component_index);
+
+        if let Some(bitset) = &mut self.bitset {
+            bitset.remove(entity_id);
+        }
+        self.record_change(entity_id, ChangeKind::Removed);
+        Some(entity_component_data)
+    }
+
+    /// Drops the tombstones and gives back spare capacity, the components after a tombstone
+    /// get new indices.
+    pub(super) fn compact(&mut self) {
+        if matches!(self.components, ComponentsLayout::Tombstoned { .. }) {
+            self.components.pack();
+            self.reindex();
         }
+        self.components.shrink_to_fit();
+        self.entity_component_map.shrink_to_fit();
     }
-}
\ No newline at end of file
+
+    /// Maps every entity to its position, for after the components were moved in bulk.
+    fn reindex(&mut self) {
+        if matches!(self.components, ComponentsLayout::Tags(_)) {
+            return;
+        }
+        self.entity_component_map.clear();
+        for (index, entity_id) in self.components.entity_ids().enumerate() {
+            self.entity_component_map.insert(entity_id, index);
+        }
+    }
+}
+
+impl<C: 'static> Storage<C> for ComponentsStorage<C> {
+    fn get(&self, entity_id: EntityId) -> Option<&C> {
+        self.slot_of(entity_id)
+            .map(|index| self.components.component_at(index))
+    }
+
+    fn get_mut(&mut self, entity_id: EntityId) -> Option<&mut C> {
+        let index = self.slot_of(entity_id)?;
+        self.record_change(entity_id, ChangeKind::Modified);
+        Some(self.components.component_at_mut(index))
+    }
+
+    fn get_mut_with_changes(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Option<(&mut C, &mut Option<ChangeLog>)> {
+        let index = self.slot_of(entity_id)?;
+        Some((self.components.component_at_mut(index), &mut self.changes))
+    }
+
+    fn contains(&self, entity_id: EntityId) -> bool {
+        self.slot_of(entity_id).is_some()
+    }
+
+    /// Appends the component, the entity must not already have one.
+    fn insert(&mut self, entity_id: EntityId, component_data: C) {
+        debug_assert!(!self.contains(entity_id));
+        let index = self.components.push(entity_id, component_data);
+        if !matches!(self.components, ComponentsLayout::Tags(_)) {
+            self.entity_component_map.insert(entity_id, index);
+        }
+        if let Some(bitset) = &mut self.bitset {
+            bitset.insert(entity_id);
+        }
+        self.record_change(entity_id, ChangeKind::Added);
+    }
+
+    /// Swap-removes the entity's component, keeping `components` packed.
+    ///
+    /// The last component is moved into the freed slot and its index is fixed up, so afterwards
+    /// every other entity still maps to its own component, only the order changes. While the
+    /// layout has tombstones it's a stable removal instead, nothing moves.
+    fn remove(&mut self, entity_id: EntityId) -> Option<C> {
+        let entity_component_index = self.unmap(entity_id)?;
+        let entity_component_data = self.components.swap_remove(entity_component_index);
+
+        // Nothing was moved if the entity's component was the last one.
+        if let Some(moved_entity_id) = self.components.entity_id_at(entity_component_index) {
+            *self
+                .entity_component_map
+                .get_mut(&moved_entity_id)
+                .expect("Every stored component is mapped") = entity_component_index;
+        }
+
+        if let Some(bitset) = &mut self.bitset {
+            bitset.remove(entity_id);
+        }
+        self.record_change(entity_id, ChangeKind::Removed);
+        Some(entity_component_data)
+    }
+
+    fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (EntityId, &C)> {
+        self.components.iter()
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut C)> {
+        self.components.iter_mut()
+    }
+
+    fn slots_mut(&mut self) -> SlotsMut<'_, C> {
+        self.components.slots_mut(&self.entity_component_map)
+    }
+
+    fn bitset(&self) -> Option<&EntityBitset> {
+        match &self.components {
+            ComponentsLayout::Tags(tags) => Some(tags.bitset()),
+            _ => self.bitset.as_ref(),
+        }
+    }
+}
+
+impl<C: 'static> AnyComponentsStorage for ComponentsStorage<C> {
+    fn component_type_name(&self) -> &'static str {
+        std::any::type_name::<C>()
+    }
+
+    fn contains_entity(&self, entity_id: EntityId) -> bool {
+        self.contains(entity_id)
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.components.memory_usage()
+            + self.entity_component_map.memory_usage()
+            + self.bitset.as_ref().map_or(0, EntityBitset::memory_usage)
+            + self
+                .pool
+                .as_ref()
+                .map_or(0, |pool| pool.capacity() * std::mem::size_of::<C>())
+            + self.changes.as_ref().map_or(0, |changes| {
+                changes.capacity() * std::mem::size_of::<(EntityId, ChangeKind)>()
+            })
+    }
+
+    fn check_consistency(&self, entity_validity_set: &EcsHashSet<EntityId>) -> Result<(), String> {
+        let name = std::any::type_name::<C>();
+
+        if let ComponentsLayout::Tags(tags) = &self.components {
+            if tags.entity_ids().count() != tags.len() {
+                return Err(format!(
+                    "Component {} counts {} tags but has {} set",
+                    name,
+                    tags.len(),
+                    tags.entity_ids().count()
+                ));
+            }
+            if let Some(entity_id) = tags
+                .entity_ids()
+                .find(|entity_id| !entity_validity_set.contains(entity_id))
+            {
+                return Err(format!(
+                    "Component {} is stored for entity {} which is invalid",
+                    name, entity_id
+                ));
+            }
+            return Ok(());
+        }
+
+        // With every mapped index checked below, equal lengths also rule out duplicates.
+        if self.entity_component_map.len() != self.components.len() {
+            return Err(format!(
+                "Component {} maps {} entities but stores {} components",
+                name,
+                self.entity_component_map.len(),
+                self.components.len()
+            ));
+        }
+
+        for (entity_id, index) in self.entity_component_map.iter() {
+            match self.components.entity_id_at(index) {
+                Some(stored_entity_id) if stored_entity_id == entity_id => {}
+                Some(stored_entity_id) => {
+                    return Err(format!(
+                        "Component {} maps entity {} to slot {} which belongs to entity {}",
+                        name, entity_id, index, stored_entity_id
+                    ));
+                }
+                None => {
+                    return Err(format!(
+                        "Component {} maps entity {} to slot {} which is out of bounds or empty",
+                        name, entity_id, index
+                    ));
+                }
+            }
+
+            if !entity_validity_set.contains(&entity_id) {
+                return Err(format!(
+                    "Component {} is stored for entity {} which is invalid",
+                    name, entity_id
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove_entity(&mut self, entity_id: EntityId) -> bool {
+        let Some(component_data) = self.remove(entity_id) else {
+            return false;
+        };
+        self.recycle(component_data);
+        true
+    }
+
+    fn retain_entities(&mut self, keep: &mut dyn FnMut(EntityId) -> bool) {
+        let removed: Vec<EntityId> = self
+            .components
+            .entity_ids()
+            .filter(|entity_id| !keep(*entity_id))
+            .collect();
+
+        for entity_id in removed {
+            self.remove_entity(entity_id);
+        }
+    }
+
+    fn for_each_entity(&self, f: &mut dyn FnMut(EntityId)) {
+        for entity_id in self.components.entity_ids() {
+            f(entity_id);
+        }
+    }
+
+    fn remap_entities(&mut self, id_map: &HashMap<EntityId, EntityId>) {
+        self.retain_entities(&mut |entity_id| id_map.contains_key(&entity_id));
+        self.components.pack();
+        self.components
+            .remap_entity_ids(|entity_id| id_map[&entity_id]);
+        self.reindex();
+        if self.bitset.is_some() {
+            self.enable_bitset();
+        }
+        if let Some(changes) = &mut self.changes {
+            changes.retain_mut(|(entity_id, _)| match id_map.get(entity_id) {
+                Some(&new_entity_id) => {
+                    *entity_id = new_entity_id;
+                    true
+                }
+                None => false,
+            });
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+
+use super::id_types::{ComponentId, EntityId};
+use super::type_registry::TypeRegistry;
+use super::world::World;
+
+/// The structural difference between two worlds, from [`World::diff`]. Every list is sorted
+/// by entity id, and the component types of one entity by type name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorldDiff {
+    /// Live in `self` but not in `other`.
+    pub only_in_self: Vec<EntityId>,
+    /// Live in `other` but not in `self`.
+    pub only_in_other: Vec<EntityId>,
+    /// Component types shared entities have in `other` but not in `self`.
+    pub added: Vec<(EntityId, ComponentId)>,
+    /// Component types shared entities have in `self` but not in `other`.
+    pub removed: Vec<(EntityId, ComponentId)>,
+    /// Components both have with different values, only for types registered with
+    /// [`TypeRegistry::register_comparable`].
+    pub changed: Vec<(EntityId, ComponentId)>,
+}
+
+impl WorldDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl World {
+    /// What it takes to go from `self` to `other`, entities are matched by id, e.g. a world
+    /// and its [`World::deep_clone`] after a few frames. Meant for test assertions and
+    /// debugging netcode, it's `O(n)` over every component of both worlds.
+    pub fn diff(&self, other: &World, registry: &TypeRegistry) -> WorldDiff {
+        let mut diff = WorldDiff::default();
+
+        let mut entity_ids: Vec<EntityId> = self
+            .entity_validity_set
+            .union(&other.entity_validity_set)
+            .copied()
+            .collect();
+        entity_ids.sort();
+
+        for entity_id in entity_ids {
+            let (Ok(signature), Ok(other_signature)) = (
+                self.entity_signature(entity_id),
+                other.entity_signature(entity_id),
+            ) else {
+                if self.is_entity_valid(entity_id) {
+                    diff.only_in_self.push(entity_id);
+                } else {
+                    diff.only_in_other.push(entity_id);
+                }
+                continue;
+            };
+
+            let types: HashSet<ComponentId> = signature.iter().copied().collect();
+            let other_types: HashSet<ComponentId> = other_signature.iter().copied().collect();
+            for &component_id in &other_signature {
+                if !types.contains(&component_id) {
+                    diff.added.push((entity_id, component_id));
+                }
+            }
+            for &component_id in &signature {
+                if !other_types.contains(&component_id) {
+                    diff.removed.push((entity_id, component_id));
+                } else if registry
+                    .compare_shims
+                    .get(&component_id)
+                    .is_some_and(|differ| differ(self, other, entity_id))
+                {
+                    diff.changed.push((entity_id, component_id));
+                }
+            }
+        }
+
+        diff
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::TryReserveError;
+
+use super::hashing::EcsHashMap;
+use super::id_types::EntityId;
+
+/// Where each entity's component sits in a storage, the subset of the `HashMap` API the
+/// storages use so both modes look the same to them.
+#[derive(Clone)]
+pub(super) enum EntityIndex {
+    Sparse(EcsHashMap<EntityId, usize>),
+    /// Slot `i` is the generation and component index of the entity with index `i`, for
+    /// worlds made with `World::with_dense_capacity`. No hashing, but a slot for every
+    /// possible entity.
+    Dense {
+        indices: Vec<Option<(u32, usize)>>,
+        len: usize,
+    },
+}
+
+impl EntityIndex {
+    /// Dense with room for `max_entities` when it's given, sparse otherwise.
+    pub(super) fn new(max_entities: Option<usize>) -> Self {
+        match max_entities {
+            Some(max_entities) => Self::Dense {
+                indices: vec![None; max_entities],
+                len: 0,
+            },
+            None => Self::Sparse(EcsHashMap::default()),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Self::Sparse(map) => map.len(),
+            Self::Dense { len, .. } => *len,
+        }
+    }
+
+    pub(super) fn get(&self, entity_id: &EntityId) -> Option<&usize> {
+        match self {
+            Self::Sparse(map) => map.get(entity_id),
+            Self::Dense { indices, .. } => match indices.get(entity_id.index)? {
+                Some((generation, index)) if *generation == entity_id.generation => Some(index),
+                _ => None,
+            },
+        }
+    }
+
+    pub(super) fn get_mut(&mut self, entity_id: &EntityId) -> Option<&mut usize> {
+        match self {
+            Self::Sparse(map) => map.get_mut(entity_id),
+            Self::Dense { indices, .. } => match indices.get_mut(entity_id.index)? {
+                Some((generation, index)) if *generation == entity_id.generation => Some(index),
+                _ => None,
+            },
+        }
+    }
+
+    pub(super) fn contains_key(&self, entity_id: &EntityId) -> bool {
+        self.get(entity_id).is_some()
+    }
+
+    /// The world never creates entities past the dense capacity, so for it an id out of range
+    /// is a bug, not an error. Neither are two live entities sharing an index.
+    pub(super) fn insert(&mut self, entity_id: EntityId, index: usize) -> Option<usize> {
+        match self {
+            Self::Sparse(map) => map.insert(entity_id, index),
+            Self::Dense { indices, len } => {
+                let old = indices[entity_id.index].replace((entity_id.generation, index));
+                if old.is_none() {
+                    *len += 1;
+                }
+                old.map(|(_, old_index)| old_index)
+            }
+        }
+    }
+
+    pub(super) fn remove(&mut self, entity_id: &EntityId) -> Option<usize> {
+        match self {
+            Self::Sparse(map) => map.remove(entity_id),
+            Self::Dense { indices, len } => {
+                let slot = indices.get_mut(entity_id.index)?;
+                let (_, old_index) =
+                    slot.take_if(|(generation, _)| *generation == entity_id.generation)?;
+                *len -= 1;
+                Some(old_index)
+            }
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        match self {
+            Self::Sparse(map) => map.clear(),
+            Self::Dense { indices, len } => {
+                indices.fill(None);
+                *len = 0;
+            }
+        }
+    }
+
+    /// In no particular order for the sparse mode, in index order for the dense one.
+    pub(super) fn iter(&self) -> impl Iterator<Item = (EntityId, usize)> {
+        let (sparse, dense) = match self {
+            Self::Sparse(map) => (Some(map.iter()), None),
+            Self::Dense { indices, .. } => (None, Some(indices.iter().enumerate())),
+        };
+
+        sparse
+            .into_iter()
+            .flatten()
+            .map(|(entity_id, index)| (*entity_id, *index))
+            .chain(dense.into_iter().flatten().filter_map(|(slot, entry)| {
+                let (generation, index) = (*entry)?;
+                Some((EntityId::from_raw_parts(slot, generation), index))
+            }))
+    }
+
+    /// The dense mode is allocated up front, it never grows.
+    pub(super) fn reserve(&mut self, additional: usize) {
+        if let Self::Sparse(map) = self {
+            map.reserve(additional);
+        }
+    }
+
+    pub(super) fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        match self {
+            Self::Sparse(map) => map.try_reserve(additional),
+            Self::Dense { .. } => Ok(()),
+        }
+    }
+
+    pub(super) fn shrink_to_fit(&mut self) {
+        if let Self::Sparse(map) = self {
+            map.shrink_to_fit();
+        }
+    }
+
+    /// Rough number of bytes allocated.
+    pub(super) fn memory_usage(&self) -> usize {
+        match self {
+            // A `hashbrown` table, a control byte per bucket on top of the entry.
+            Self::Sparse(map) => map.capacity() * (std::mem::size_of::<(EntityId, usize)>() + 1),
+            Self::Dense { indices, .. } => {
+                indices.capacity() * std::mem::size_of::<Option<(u32, usize)>>()
+            }
+        }
+    }
+}
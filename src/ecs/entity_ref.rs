@@ -0,0 +1,79 @@
+use super::component_storage::Storage;
+use super::error::Error;
+use super::id_types::EntityId;
+use super::world::World;
+
+/// Read-only access to the components of a single, already validated, entity.
+pub struct EntityRef<'w> {
+    world: &'w World,
+    entity_id: EntityId,
+}
+
+impl<'w> EntityRef<'w> {
+    pub(super) fn new(world: &'w World, entity_id: EntityId) -> Self {
+        Self { world, entity_id }
+    }
+
+    pub fn id(&self) -> EntityId {
+        self.entity_id
+    }
+
+    pub fn get<C: 'static>(&self) -> Option<&'w C> {
+        self.world.get_component_storage::<C>()?.get(self.entity_id)
+    }
+
+    pub fn has<C: 'static>(&self) -> bool {
+        self.get::<C>().is_some()
+    }
+}
+
+/// Mutable access to a single, already validated, entity.
+pub struct EntityMut<'w> {
+    world: &'w mut World,
+    entity_id: EntityId,
+}
+
+impl<'w> EntityMut<'w> {
+    pub(super) fn new(world: &'w mut World, entity_id: EntityId) -> Self {
+        Self { world, entity_id }
+    }
+
+    pub fn id(&self) -> EntityId {
+        self.entity_id
+    }
+
+    pub fn get<C: 'static>(&self) -> Option<&C> {
+        self.world.get_component_storage::<C>()?.get(self.entity_id)
+    }
+
+    pub fn get_mut<C: 'static>(&mut self) -> Option<&mut C> {
+        self.world
+            .get_component_storage_mut::<C>()?
+            .get_mut(self.entity_id)
+    }
+
+    pub fn has<C: 'static>(&self) -> bool {
+        self.get::<C>().is_some()
+    }
+
+    /// Panics if the entity already has `C`, use [`EntityMut::try_insert`] if that's possible.
+    pub fn insert<C: 'static>(&mut self, component_data: C) -> &mut Self {
+        if let Err(error) = self.try_insert(component_data) {
+            panic!("{}", error);
+        }
+        self
+    }
+
+    pub fn try_insert<C: 'static>(&mut self, component_data: C) -> Result<&mut Self, Error> {
+        self.world
+            .add_valid_entity_component(self.entity_id, component_data)?;
+        Ok(self)
+    }
+
+    /// Removes the entity and all of its components, consuming the handle.
+    pub fn despawn(self) {
+        self.world
+            .despawn_entity(self.entity_id)
+            .expect("The handle is only created for valid entities");
+    }
+}
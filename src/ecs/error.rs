@@ -1,17 +1,68 @@
 use super::id_types::EntityId;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     InvalidEntityId(EntityId),
+    /// The component type was never registered, neither by an add nor by
+    /// `World::ensure_component_registered`. Registration is never undone.
     InvalidWorldComponent(&'static str),
+    /// The type is registered but the entity doesn't have it.
     InvalidEntityComponent(&'static str, EntityId),
     ComponentAlreadyAdded(&'static str, EntityId),
+    /// Making the second entity the parent of the first would create a cycle.
+    HierarchyCycle(EntityId, EntityId),
+    /// The same entity was passed twice where distinct entities are required.
+    DuplicateEntityId(EntityId),
+    EntityAlreadyExists(EntityId),
+    OutOfEntityIds,
+    /// The validator registered for the component rejected it, with its reason.
+    ComponentValidationFailed(&'static str, String),
+    /// The same component type was passed twice where distinct types are required.
+    DuplicateComponentType(&'static str),
+    /// `Parent` and `Children` can only be changed through the hierarchy methods,
+    /// like `World::set_parent`.
+    HierarchyComponent(&'static str),
+    /// There was no memory left for the component's storage to grow, only returned by the
+    /// `try_` methods that reserve fallibly.
+    AllocationFailed(&'static str),
+}
+
+impl Error {
+    pub fn is_invalid_entity(&self) -> bool {
+        matches!(self, Error::InvalidEntityId(_))
+    }
+
+    /// `true` both when the component type is unknown and when the entity just lacks it.
+    pub fn is_missing_component(&self) -> bool {
+        matches!(
+            self,
+            Error::InvalidWorldComponent(_) | Error::InvalidEntityComponent(_, _)
+        )
+    }
+
+    /// The entity the error is about, for [`Error::HierarchyCycle`] it's the child.
+    pub fn entity_id(&self) -> Option<EntityId> {
+        match self {
+            Error::InvalidEntityId(entity_id)
+            | Error::InvalidEntityComponent(_, entity_id)
+            | Error::ComponentAlreadyAdded(_, entity_id)
+            | Error::HierarchyCycle(entity_id, _)
+            | Error::DuplicateEntityId(entity_id)
+            | Error::EntityAlreadyExists(entity_id) => Some(*entity_id),
+            Error::InvalidWorldComponent(_)
+            | Error::OutOfEntityIds
+            | Error::ComponentValidationFailed(_, _)
+            | Error::DuplicateComponentType(_)
+            | Error::HierarchyComponent(_)
+            | Error::AllocationFailed(_) => None,
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::InvalidEntityId(entity_id) => write!(f, "Entity {} is invalid", entity_id.0),
+            Error::InvalidEntityId(entity_id) => write!(f, "Entity {} is invalid", entity_id),
             Error::InvalidWorldComponent(name) => {
                 write!(
                     f,
@@ -20,15 +71,41 @@ impl std::fmt::Display for Error {
                 )
             }
             Error::InvalidEntityComponent(name, entity_id) => {
-                write!(f, "Component {} was never registered to the entity {}", name, entity_id.0)
+                write!(f, "Component {} was never registered to the entity {}", name, entity_id)
             }
             Error::ComponentAlreadyAdded(name, entity_id) => {
                 write!(
                     f,
                     "Component {} was already added to entity {}",
-                    name, entity_id.0
+                    name, entity_id
                 )
             }
+            Error::HierarchyCycle(child, parent) => {
+                write!(
+                    f,
+                    "Entity {} can't be the parent of entity {}, it would create a cycle",
+                    parent, child
+                )
+            }
+            Error::DuplicateEntityId(entity_id) => {
+                write!(f, "Entity {} was passed more than once", entity_id)
+            }
+            Error::EntityAlreadyExists(entity_id) => {
+                write!(f, "Entity {} already exists", entity_id)
+            }
+            Error::OutOfEntityIds => write!(f, "All entity ids were used up"),
+            Error::ComponentValidationFailed(name, reason) => {
+                write!(f, "Component {} failed validation: {}", name, reason)
+            }
+            Error::DuplicateComponentType(name) => {
+                write!(f, "Component {} was passed more than once", name)
+            }
+            Error::HierarchyComponent(name) => {
+                write!(f, "Component {} can only be changed through the hierarchy methods", name)
+            }
+            Error::AllocationFailed(name) => {
+                write!(f, "Couldn't allocate room for component {}", name)
+            }
         }
     }
 }
@@ -0,0 +1,12 @@
+use std::collections::{HashMap, HashSet};
+
+/// With the `deterministic` feature the maps and sets use a fixed seed, so two worlds built
+/// the same way iterate them the same way, e.g. for lockstep networking.
+#[cfg(feature = "deterministic")]
+pub(super) type EcsBuildHasher =
+    std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+#[cfg(not(feature = "deterministic"))]
+pub(super) type EcsBuildHasher = std::collections::hash_map::RandomState;
+
+pub(super) type EcsHashMap<K, V> = HashMap<K, V, EcsBuildHasher>;
+pub(super) type EcsHashSet<K> = HashSet<K, EcsBuildHasher>;
@@ -0,0 +1,268 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use super::component_storage::{Storage, StorageCloneFn, clone_storage};
+use super::error::Error;
+use super::id_types::{ComponentId, EntityId};
+use super::world::World;
+
+/// Maintained by [`World::set_parent`], so it can't be constructed directly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parent(pub(super) EntityId);
+
+impl Parent {
+    pub fn id(&self) -> EntityId {
+        self.0
+    }
+}
+
+/// Maintained by [`World::set_parent`], so it can't be constructed directly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Children(pub(super) Vec<EntityId>);
+
+impl Children {
+    pub fn ids(&self) -> &[EntityId] {
+        &self.0
+    }
+}
+
+/// [`Parent`] or [`Children`], which only the hierarchy methods may change.
+pub(super) fn is_hierarchy_component(component_id: ComponentId) -> bool {
+    component_id == ComponentId::of::<Parent>() || component_id == ComponentId::of::<Children>()
+}
+
+impl World {
+    /// Lets [`World::deep_clone`] and snapshots copy the hierarchy. Unlike
+    /// [`World::register_clonable`] it leaves [`World::clone_entity`] out, a copy can't take
+    /// the original's place in the tree.
+    pub(super) fn register_hierarchy_shims(&mut self) {
+        let shims: [(ComponentId, StorageCloneFn); 2] = [
+            (ComponentId::of::<Parent>(), clone_storage::<Parent>),
+            (ComponentId::of::<Children>(), clone_storage::<Children>),
+        ];
+        for (component_id, clone_fn) in shims {
+            self.clone_shims.insert(component_id, clone_fn);
+            self.snapshot_shims.insert(component_id, clone_fn);
+        }
+    }
+
+    /// Makes `parent` the parent of `child`, detaching `child` from its old parent if it had one.
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> Result<(), Error> {
+        for entity_id in [child, parent] {
+            if !self.is_entity_valid(entity_id) {
+                return Err(Error::InvalidEntityId(entity_id));
+            }
+        }
+
+        // Walk up from the new parent, if we meet the child it's a cycle.
+        let mut ancestor = Some(parent);
+        while let Some(entity_id) = ancestor {
+            if entity_id == child {
+                return Err(Error::HierarchyCycle(child, parent));
+            }
+            ancestor = self.parent_of(entity_id);
+        }
+
+        self.remove_parent(child)?;
+
+        self.add_valid_entity_component(child, Parent(parent))?;
+        match self.get_entity_component_mut::<Children>(parent) {
+            Ok(children) => children.0.push(child),
+            Err(_) => self.add_valid_entity_component(parent, Children(vec![child]))?,
+        }
+
+        Ok(())
+    }
+
+    /// Makes `child` a root, returning its old parent.
+    pub fn remove_parent(&mut self, child: EntityId) -> Result<Option<EntityId>, Error> {
+        if !self.is_entity_valid(child) {
+            return Err(Error::InvalidEntityId(child));
+        }
+
+        // The parent's `Children` is patched by `unlink_removed_component`.
+        Ok(self
+            .take_hierarchy_component::<Parent>(child)
+            .map(|parent| parent.0))
+    }
+
+    pub fn parent_of(&self, entity_id: EntityId) -> Option<EntityId> {
+        self.get_component_storage::<Parent>()?
+            .get(entity_id)
+            .map(Parent::id)
+    }
+
+    /// Every active entity's `C` along with its parent's `C`, `None` when the entity has no
+    /// parent or the parent has no `C`. In `C`'s storage order, parents aren't visited first.
+    pub fn query_with_parent<C: 'static>(
+        &self,
+    ) -> impl Iterator<Item = (EntityId, &C, Option<&C>)> {
+        let component_storage = self.get_component_storage::<C>();
+        let parent_storage = self.get_component_storage::<Parent>();
+
+        component_storage
+            .into_iter()
+            .flat_map(|component_storage| component_storage.components.iter())
+            .filter(|(entity_id, _)| !self.inactive_set.contains(entity_id))
+            .map(move |(entity_id, component)| {
+                let parent_component = parent_storage
+                    .and_then(|parent_storage| parent_storage.get(entity_id))
+                    .and_then(|parent| component_storage?.get(parent.0));
+                (entity_id, component, parent_component)
+            })
+    }
+
+    /// Despawns `root` and all of its descendants.
+    pub fn despawn_recursive(&mut self, root: EntityId) -> Result<(), Error> {
+        if !self.is_entity_valid(root) {
+            return Err(Error::InvalidEntityId(root));
+        }
+
+        let mut subtree = vec![root];
+        let mut i = 0;
+        while i < subtree.len() {
+            if let Ok(children) = self.get_entity_component::<Children>(subtree[i]) {
+                subtree.extend_from_slice(&children.0);
+            }
+            i += 1;
+        }
+
+        // Leaves first, so no entity gets reattached to a despawned one.
+        for entity_id in subtree.into_iter().rev() {
+            self.despawn_entity(entity_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Despawns only `entity_id`, its children move up to its parent, or become roots if it
+    /// was one. They're appended to the parent's [`Children`] in their old order.
+    pub fn despawn_reparent(&mut self, entity_id: EntityId) -> Result<(), Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        let grandparent = self.parent_of(entity_id);
+        let children = self
+            .get_entity_component::<Children>(entity_id)
+            .map(|children| children.0.clone())
+            .unwrap_or_default();
+
+        // Leaves the children as roots.
+        self.despawn_entity(entity_id)?;
+
+        if let Some(grandparent) = grandparent {
+            for child in children {
+                // It was the grandparent's descendant, so this can't close a cycle.
+                self.set_parent(child, grandparent)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites the ids inside [`Parent`] and [`Children`] for [`World::compact_ids`], ids
+    /// missing from `id_map` are dropped.
+    pub(super) fn remap_hierarchy(&mut self, id_map: &HashMap<EntityId, EntityId>) {
+        self.retain_hierarchy_ids(|entity_id| id_map.get(&entity_id).copied());
+    }
+
+    /// Drops the [`Parent`] and [`Children`] entries pointing at despawned entities, e.g.
+    /// after [`World::restore`] brought back fewer entities than there are now.
+    pub(super) fn prune_hierarchy(&mut self) {
+        let entity_validity_set = std::mem::take(&mut self.entity_validity_set);
+        self.retain_hierarchy_ids(|entity_id| {
+            entity_validity_set
+                .contains(&entity_id)
+                .then_some(entity_id)
+        });
+        self.entity_validity_set = entity_validity_set;
+    }
+
+    /// Replaces every id inside [`Parent`] and [`Children`] with what `map` returns, `None`
+    /// drops it. An entity left without a parent or children loses the component, it's bulk
+    /// bookkeeping like [`World::clear_component`] so `on_remove` hooks don't run.
+    fn retain_hierarchy_ids(&mut self, mut map: impl FnMut(EntityId) -> Option<EntityId>) {
+        if let Some(parents) = self.get_component_storage_mut::<Parent>() {
+            let mut orphans = Vec::new();
+            for (entity_id, parent) in parents.components.iter_mut() {
+                match map(parent.0) {
+                    Some(new_parent) => parent.0 = new_parent,
+                    None => orphans.push(entity_id),
+                }
+            }
+            for entity_id in orphans {
+                parents.remove(entity_id);
+            }
+        }
+
+        if let Some(children) = self.get_component_storage_mut::<Children>() {
+            let mut childless = Vec::new();
+            for (entity_id, children) in children.components.iter_mut() {
+                children.0 = children.0.iter().filter_map(|&child| map(child)).collect();
+                if children.0.is_empty() {
+                    childless.push(entity_id);
+                }
+            }
+            for entity_id in childless {
+                children.remove(entity_id);
+            }
+        }
+    }
+
+    /// Keeps the hierarchy consistent before an entity is despawned, its parent forgets
+    /// about it and its children become roots.
+    pub(super) fn detach_from_hierarchy(&mut self, entity_id: EntityId) {
+        self.take_hierarchy_component::<Parent>(entity_id);
+        self.take_hierarchy_component::<Children>(entity_id);
+    }
+
+    /// Called with every component removed from an entity, it's a no-op unless it's a
+    /// [`Parent`] or [`Children`]. The other end of the link is updated, so taking either off
+    /// through the generic removal methods leaves the hierarchy consistent.
+    pub(super) fn unlink_removed_component<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+        component: &C,
+    ) {
+        let component = component as &dyn Any;
+        if let Some(&Parent(parent)) = component.downcast_ref::<Parent>() {
+            let Ok(children) = self.get_entity_component_mut::<Children>(parent) else {
+                return;
+            };
+            children.0.retain(|&child| child != entity_id);
+            if children.0.is_empty() {
+                self.take_hierarchy_component::<Children>(parent);
+            }
+        } else if let Some(Children(children)) = component.downcast_ref::<Children>() {
+            for &child in children {
+                if self.parent_of(child) == Some(entity_id) {
+                    self.take_hierarchy_component::<Parent>(child);
+                }
+            }
+        }
+    }
+
+    /// Called before `C` is cleared or drained in bulk. Without any [`Parent`] there are no
+    /// [`Children`] either and the other way around, so the other one is cleared too.
+    pub(super) fn unlink_cleared_component<C: 'static>(&mut self) {
+        let component_id = ComponentId::of::<C>();
+        if component_id == ComponentId::of::<Parent>() {
+            if let Some(children) = self.get_component_storage_mut::<Children>() {
+                children.clear();
+            }
+        } else if component_id == ComponentId::of::<Children>()
+            && let Some(parents) = self.get_component_storage_mut::<Parent>()
+        {
+            parents.clear();
+        }
+    }
+
+    /// Takes `C` off the entity like [`World::remove_entity_component`], so `on_remove` hooks
+    /// run, but without the checks, the hierarchy knows the entity is valid.
+    fn take_hierarchy_component<C: 'static>(&mut self, entity_id: EntityId) -> Option<C> {
+        let component = self.get_component_storage_mut::<C>()?.remove(entity_id)?;
+        self.component_removed(entity_id, &component);
+        Some(component)
+    }
+}
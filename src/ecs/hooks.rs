@@ -0,0 +1,121 @@
+use std::any::Any;
+
+use super::error::Error;
+use super::id_types::{ComponentId, EntityId};
+use super::world::World;
+
+type ComponentHook = Box<dyn FnMut(EntityId)>;
+/// Gets the component as `&dyn Any` so one map can hold validators of every type.
+type ComponentValidator = Box<dyn Fn(&dyn Any) -> Result<(), String>>;
+
+#[derive(Default)]
+pub(super) struct ComponentHooks {
+    on_add: Vec<ComponentHook>,
+    on_remove: Vec<ComponentHook>,
+    validator: Option<ComponentValidator>,
+}
+
+/// Callbacks registered with [`World::on_entity_created`] and [`World::on_entity_despawned`].
+#[derive(Default)]
+pub(super) struct EntityHooks {
+    on_created: Vec<ComponentHook>,
+    on_despawned: Vec<ComponentHook>,
+}
+
+impl EntityHooks {
+    pub(super) fn run_on_created(&mut self, entity_id: EntityId) {
+        for hook in &mut self.on_created {
+            hook(entity_id);
+        }
+    }
+
+    pub(super) fn run_on_despawned(&mut self, entity_id: EntityId) {
+        for hook in &mut self.on_despawned {
+            hook(entity_id);
+        }
+    }
+}
+
+impl ComponentHooks {
+    pub(super) fn run_on_add(&mut self, entity_id: EntityId) {
+        for hook in &mut self.on_add {
+            hook(entity_id);
+        }
+    }
+
+    pub(super) fn run_on_remove(&mut self, entity_id: EntityId) {
+        for hook in &mut self.on_remove {
+            hook(entity_id);
+        }
+    }
+}
+
+impl World {
+    /// Makes every insert of `C` call `validator` first, an `Err` rejects the component with
+    /// [`Error::ComponentValidationFailed`] before anything is stored.
+    /// There's one validator per type, registering another replaces it.
+    pub fn register_validator<C: 'static>(
+        &mut self,
+        validator: impl Fn(&C) -> Result<(), String> + 'static,
+    ) {
+        self.component_hooks
+            .entry(ComponentId::of::<C>())
+            .or_default()
+            .validator = Some(Box::new(move |component| {
+            validator(
+                component
+                    .downcast_ref::<C>()
+                    .expect("The validator is registered under the component's own id"),
+            )
+        }));
+    }
+
+    pub(super) fn validate_component<C: 'static>(&self, component: &C) -> Result<(), Error> {
+        let Some(validator) = self
+            .component_hooks
+            .get(&ComponentId::of::<C>())
+            .and_then(|component_hooks| component_hooks.validator.as_ref())
+        else {
+            return Ok(());
+        };
+
+        validator(component)
+            .map_err(|reason| Error::ComponentValidationFailed(std::any::type_name::<C>(), reason))
+    }
+
+    /// Calls `hook` every time `C` is added to an entity, after it was stored.
+    ///
+    /// Hooks only get the entity id, they can't borrow the world, so anything they need to
+    /// know has to be captured or shared with them, e.g. through an `Rc<RefCell<_>>`.
+    pub fn on_add<C: 'static>(&mut self, hook: impl FnMut(EntityId) + 'static) {
+        self.component_hooks
+            .entry(ComponentId::of::<C>())
+            .or_default()
+            .on_add
+            .push(Box::new(hook));
+    }
+
+    /// Calls `hook` every time `C` is removed from an entity, including when the entity is
+    /// despawned, after the component is gone.
+    pub fn on_remove<C: 'static>(&mut self, hook: impl FnMut(EntityId) + 'static) {
+        self.component_hooks
+            .entry(ComponentId::of::<C>())
+            .or_default()
+            .on_remove
+            .push(Box::new(hook));
+    }
+
+    /// Calls `hook` with every entity created from now on, by any of the `create_entity`
+    /// methods or by spawning, once per id even for batches.
+    ///
+    /// Like the component hooks it can't borrow the world. [`World::restore`] replaces the
+    /// entities wholesale and doesn't call it.
+    pub fn on_entity_created(&mut self, hook: impl FnMut(EntityId) + 'static) {
+        self.entity_hooks.on_created.push(Box::new(hook));
+    }
+
+    /// Calls `hook` with every despawned entity, after all of its components are gone.
+    pub fn on_entity_despawned(&mut self, hook: impl FnMut(EntityId) + 'static) {
+        self.entity_hooks.on_despawned.push(Box::new(hook));
+    }
+}
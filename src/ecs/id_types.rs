@@ -1,20 +1,102 @@
 use std::any::TypeId;
+use std::cmp::Ordering;
 use std::hash::Hash;
 
 /// Comparing it can be useful sometimes:
 ///
-/// - `a > b` means that `a` was allocated after `b`.
+/// - `a > b` means that `a` was allocated after `b`, see [`EntityId::allocated_after`].
 /// - `a == b` means that `a` refers to the same underlying entity as `b`.
 ///
+/// Besides the index it carries a generation. The world moves to a new one whenever it hands
+/// out an index that was used before, e.g. after a despawn or [`World::compact_ids`], and
+/// every id it hands out from then on has it. A handle kept from before that stays invalid
+/// instead of silently pointing at whichever entity got its index.
+///
 /// Non-comarison traits are mostly derived for internal use, but are there for
 /// your use too.
-#[derive(Debug, Copy, Hash, Clone, Eq, PartialEq, PartialOrd, Ord)]
-pub struct EntityId(pub(super) usize);
+///
+/// [`World::compact_ids`]: super::World::compact_ids
+#[derive(Debug, Copy, Hash, Clone, Eq, PartialEq)]
+pub struct EntityId {
+    pub(super) index: usize,
+    pub(super) generation: u32,
+}
+
+impl EntityId {
+    /// The raw number behind the id, handy for logs and network packets.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// 0 until the world first reuses an index, see [`EntityId`].
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Same as `self > other`. It holds for the ids [`World::create_entity`] hands out,
+    /// recycled or not, since a recycled one starts a newer generation that the ids after it
+    /// share. Ids picked with [`World::create_entity_with_id`] or a custom [`IdAllocator`]
+    /// only compare by their generation and number.
+    ///
+    /// [`World::create_entity`]: super::World::create_entity
+    /// [`World::create_entity_with_id`]: super::World::create_entity_with_id
+    pub fn allocated_after(&self, other: EntityId) -> bool {
+        *self > other
+    }
+
+    /// Rebuilds an id from [`EntityId::index`], with generation 0.
+    ///
+    /// Nothing is checked here, an id you made up may point to no entity or to a
+    /// completely different one than you think, the world will still tell you if it's invalid.
+    pub fn from_raw(index: usize) -> Self {
+        Self::from_raw_parts(index, 0)
+    }
+
+    /// Like [`EntityId::from_raw`] but keeps the [`EntityId::generation`] too.
+    pub fn from_raw_parts(index: usize, generation: u32) -> Self {
+        Self { index, generation }
+    }
+}
+
+/// By generation first, every id handed out after a reuse was allocated after the ones
+/// from before it.
+impl Ord for EntityId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.generation, self.index).cmp(&(other.generation, other.index))
+    }
+}
+
+impl PartialOrd for EntityId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Just the index while the generation is 0, `index` + `v` + `generation` after that.
+impl std::fmt::Display for EntityId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.generation == 0 {
+            write!(f, "{}", self.index)
+        } else {
+            write!(f, "{}v{}", self.index, self.generation)
+        }
+    }
+}
+
+/// Decides the ids [`World::create_entity`] hands out, see [`World::with_allocator`].
+///
+/// [`World::create_entity`]: super::World::create_entity
+/// [`World::with_allocator`]: super::World::with_allocator
+pub trait IdAllocator {
+    fn next(&mut self) -> EntityId;
+}
 
 /// You may say: "Bruh, you just wrapped TypeId with a different name."
 /// You are 101% correct, and I don't care, one + is that it's a unified API.
+///
+/// It's a stable handle for type-erased tooling, e.g. a map of component to debug name.
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
-pub(super) struct ComponentId(pub(super) TypeId);
+pub struct ComponentId(pub(super) TypeId);
 
 impl ComponentId {
     pub fn of<C: 'static>() -> Self {
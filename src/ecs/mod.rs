@@ -2,11 +2,48 @@ pub use error::*;
 pub use id_types::*;
 pub use component_storage::*;
 pub use world::*;
+pub use entity_ref::*;
+pub use snapshot::*;
+pub use type_registry::*;
+pub use hierarchy::*;
+pub use name::*;
+pub use sync_world::*;
+pub use changes::*;
+pub use world_view::*;
+pub use accessor::*;
+pub use visitor::*;
+pub use bundle::*;
+pub use peers::*;
+pub use checkpoint::*;
+pub use registration::*;
+pub use diff::*;
 
 mod error;
 mod id_types;
 mod component_storage;
 mod world;
+mod entity_ref;
+mod snapshot;
+mod type_registry;
+mod hierarchy;
+mod name;
+mod sync_world;
+mod changes;
+mod world_view;
+mod accessor;
+mod visitor;
+mod bundle;
+mod peers;
+mod checkpoint;
+mod registration;
+mod diff;
+mod pool;
+mod hashing;
+mod entity_index;
+mod hooks;
+mod query;
+mod bitset;
+mod tag_set;
 
 #[cfg(test)]
 mod tests;
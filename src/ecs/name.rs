@@ -0,0 +1,72 @@
+use std::any::Any;
+
+use super::hashing::EcsHashMap;
+use super::id_types::EntityId;
+use super::world::World;
+
+/// A label for finding entities with [`World::find_by_name`].
+///
+/// Names don't have to be unique, the entity that was given a name last is the one found, and
+/// once it loses the name the one given it before. Renaming in place through
+/// `get_entity_component_mut` isn't seen by the index, use
+/// [`World::replace_entity_component`] instead.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Name(pub String);
+
+/// The name to index when `component` is stored, only [`Name`] has one.
+pub(super) fn indexed_name<C: 'static>(component: &C) -> Option<String> {
+    (component as &dyn Any)
+        .downcast_ref::<Name>()
+        .map(|name| name.0.clone())
+}
+
+impl World {
+    pub fn find_by_name(&self, name: &str) -> Option<EntityId> {
+        self.name_index
+            .get(name)
+            .and_then(|entity_ids| entity_ids.last())
+            .copied()
+    }
+
+    /// Called with every component removed from an entity, it's a no-op unless it's a [`Name`].
+    pub(super) fn unindex_removed_component<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+        component: &C,
+    ) {
+        if let Some(name) = (component as &dyn Any).downcast_ref::<Name>() {
+            self.unindex_name(&name.0, entity_id);
+        }
+    }
+
+    /// Rebuilds the index from the [`Name`] storage, for when the index can't be patched, e.g.
+    /// after [`World::restore`]. Without the history of adds, the entity later in storage
+    /// order wins a shared name.
+    pub(super) fn reindex_names(&mut self) {
+        let mut name_index = EcsHashMap::<String, Vec<EntityId>>::default();
+        if let Some(component_storage) = self.get_component_storage::<Name>() {
+            for (entity_id, name) in component_storage.components.iter() {
+                name_index
+                    .entry(name.0.clone())
+                    .or_default()
+                    .push(entity_id);
+            }
+        }
+        self.name_index = name_index;
+    }
+
+    /// Records that `entity_id` was just given the name, it's the one found until it loses it.
+    pub(super) fn index_name(&mut self, name: String, entity_id: EntityId) {
+        self.name_index.entry(name).or_default().push(entity_id);
+    }
+
+    /// Forgets that `entity_id` carries the name, the entity given it before is found again.
+    pub(super) fn unindex_name(&mut self, name: &str, entity_id: EntityId) {
+        if let Some(entity_ids) = self.name_index.get_mut(name) {
+            entity_ids.retain(|&other| other != entity_id);
+            if entity_ids.is_empty() {
+                self.name_index.remove(name);
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::any::Any;
+
+use super::changes::ChangeKind;
+use super::component_storage::{ComponentsLayout, ComponentsStorage, SlotLookup};
+use super::hashing::EcsHashSet;
+use super::id_types::{ComponentId, EntityId};
+use super::tag_set::TagSet;
+use super::world::World;
+
+/// One side of the storage around the component that's borrowed mutably.
+enum PeerSlice<'a, C> {
+    Interleaved(&'a [(EntityId, C)]),
+    SoA(&'a [EntityId], &'a [C]),
+    Tombstoned(&'a [(EntityId, Option<C>)]),
+    /// The slots `start..end` of the tags.
+    Tags {
+        tags: &'a TagSet<C>,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl<'a, C> PeerSlice<'a, C> {
+    fn get(&self, index: usize) -> Option<&'a C> {
+        match *self {
+            Self::Interleaved(component_vec) => component_vec.get(index).map(|(_, c)| c),
+            Self::SoA(_, components) => components.get(index),
+            Self::Tombstoned(slots) => slots.get(index)?.1.as_ref(),
+            Self::Tags { tags, start, end } => {
+                let index = start + index;
+                (index < end)
+                    .then(|| tags.entity_at(index))
+                    .flatten()
+                    .map(|_| TagSet::tag())
+            }
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (EntityId, &'a C)> + 'a {
+        let (interleaved, soa, tombstoned, tags) = match *self {
+            Self::Interleaved(component_vec) => (Some(component_vec.iter()), None, None, None),
+            Self::SoA(entity_ids, components) => {
+                (None, Some(entity_ids.iter().zip(components)), None, None)
+            }
+            Self::Tombstoned(slots) => (None, None, Some(slots.iter()), None),
+            Self::Tags { tags, start, end } => (
+                None,
+                None,
+                None,
+                Some((start..end).filter_map(|index| tags.entity_at(index))),
+            ),
+        };
+
+        interleaved
+            .into_iter()
+            .flatten()
+            .map(|(entity_id, component)| (*entity_id, component))
+            .chain(
+                soa.into_iter()
+                    .flatten()
+                    .map(|(entity_id, component)| (*entity_id, component)),
+            )
+            .chain(
+                tombstoned
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(entity_id, slot)| Some((*entity_id, slot.as_ref()?))),
+            )
+            .chain(
+                tags.into_iter()
+                    .flatten()
+                    .map(|entity_id| (entity_id, TagSet::tag())),
+            )
+    }
+}
+
+/// Read-only access to every other `C` while one is borrowed mutably, see
+/// [`World::for_each_with_peers`].
+pub struct Peers<'a, C> {
+    slot_lookup: SlotLookup<'a, C>,
+    inactive_set: &'a EcsHashSet<EntityId>,
+    index: usize,
+    before: PeerSlice<'a, C>,
+    after: PeerSlice<'a, C>,
+}
+
+impl<'a, C> Peers<'a, C> {
+    /// `None` for the entity currently being visited, it's the `&mut C` already.
+    pub fn get(&self, entity_id: EntityId) -> Option<&'a C> {
+        if self.inactive_set.contains(&entity_id) {
+            return None;
+        }
+
+        let peer_index = self.slot_lookup.get(entity_id)?;
+        match peer_index.cmp(&self.index) {
+            std::cmp::Ordering::Less => self.before.get(peer_index),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => self.after.get(peer_index - self.index - 1),
+        }
+    }
+
+    /// Every other active entity's `C`, in storage order.
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &'a C)> + '_ {
+        self.before
+            .iter()
+            .chain(self.after.iter())
+            .filter(|(entity_id, _)| !self.inactive_set.contains(entity_id))
+    }
+}
+
+impl World {
+    /// Visits every active entity's `C` mutably while letting `f` read all the other `C`s,
+    /// for neighbour interactions like boids.
+    ///
+    /// The storage is split around the visited component, so the `&mut C` and the
+    /// [`Peers`] never overlap. The catch is that changes are visible to the entities visited
+    /// later, peers before the current one were already updated this pass. Snapshot the
+    /// values first if every entity must see the old state.
+    pub fn for_each_with_peers<C: 'static>(
+        &mut self,
+        mut f: impl FnMut(EntityId, &mut C, &Peers<'_, C>),
+    ) {
+        let inactive_set = &self.inactive_set;
+        let Some(component_storage) = self
+            .component_storage_vecs
+            .get_mut(&ComponentId::of::<C>())
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<C>>())
+        else {
+            return;
+        };
+
+        if component_storage.changes.is_some() {
+            let active: Vec<EntityId> = component_storage
+                .components
+                .entity_ids()
+                .filter(|entity_id| !inactive_set.contains(entity_id))
+                .collect();
+            for entity_id in active {
+                component_storage.record_change(entity_id, ChangeKind::Modified);
+            }
+        }
+
+        let ComponentsStorage {
+            components,
+            entity_component_map,
+            ..
+        } = component_storage;
+
+        // Every tag is the same dangling instance, so the set is never borrowed mutably.
+        if let ComponentsLayout::Tags(tags) = components {
+            let tags = &*tags;
+            let slot_count = tags.slot_count();
+            for entity_id in tags.entity_ids() {
+                let index = entity_id.index;
+                if inactive_set.contains(&entity_id) {
+                    continue;
+                }
+
+                let peers = Peers {
+                    slot_lookup: SlotLookup::Tags(tags),
+                    inactive_set,
+                    index,
+                    before: PeerSlice::Tags {
+                        tags,
+                        start: 0,
+                        end: index,
+                    },
+                    after: PeerSlice::Tags {
+                        tags,
+                        start: index + 1,
+                        end: slot_count,
+                    },
+                };
+                f(entity_id, TagSet::tag_mut(), &peers);
+            }
+            return;
+        }
+
+        for index in 0..components.slot_count() {
+            let (entity_id, component, before, after) = match components {
+                ComponentsLayout::Interleaved(component_vec) => {
+                    let (before, rest) = component_vec.split_at_mut(index);
+                    let ((entity_id, component), after) =
+                        rest.split_first_mut().expect("The index is in bounds");
+                    (
+                        *entity_id,
+                        component,
+                        PeerSlice::Interleaved(&*before),
+                        PeerSlice::Interleaved(&*after),
+                    )
+                }
+                ComponentsLayout::SoA {
+                    entity_ids,
+                    components,
+                } => {
+                    let (before, rest) = components.split_at_mut(index);
+                    let (component, after) =
+                        rest.split_first_mut().expect("The index is in bounds");
+                    (
+                        entity_ids[index],
+                        component,
+                        PeerSlice::SoA(&entity_ids[..index], &*before),
+                        PeerSlice::SoA(&entity_ids[index + 1..], &*after),
+                    )
+                }
+                ComponentsLayout::Tags(_) => unreachable!("Tags were handled above"),
+                ComponentsLayout::Tombstoned { slots, .. } => {
+                    let (before, rest) = slots.split_at_mut(index);
+                    let ((entity_id, slot), after) =
+                        rest.split_first_mut().expect("The index is in bounds");
+                    let Some(component) = slot else {
+                        continue;
+                    };
+                    (
+                        *entity_id,
+                        component,
+                        PeerSlice::Tombstoned(&*before),
+                        PeerSlice::Tombstoned(&*after),
+                    )
+                }
+            };
+            if inactive_set.contains(&entity_id) {
+                continue;
+            }
+
+            let peers = Peers {
+                slot_lookup: SlotLookup::Map(entity_component_map),
+                inactive_set,
+                index,
+                before,
+                after,
+            };
+            f(entity_id, component, &peers);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use super::component_storage::Storage;
+use super::error::Error;
+use super::id_types::EntityId;
+use super::world::World;
+
+impl World {
+    /// Makes the world keep the `C`s it would drop, on despawn or [`World::clear_component`],
+    /// so [`World::add_pooled_component`] can hand them out again along with their heap
+    /// buffers. Components taken out with e.g. [`World::remove_entity_component`] are the
+    /// caller's and aren't pooled.
+    ///
+    /// Pooled values come back exactly as they were left, so `C` must be fine to reuse once
+    /// whatever state matters is reset.
+    pub fn register_pooled<C: Default + 'static>(&mut self) {
+        self.ensure_component_registered::<C>();
+        self.get_component_storage_mut::<C>()
+            .expect("Was just registered")
+            .pool
+            .get_or_insert_with(Vec::new);
+    }
+
+    /// Adds a `C` from the pool, or `C::default()` if the pool is empty, after `reset` got to
+    /// overwrite it, e.g. to clear a buffer while keeping its capacity.
+    pub fn add_pooled_component<C: Default + 'static>(
+        &mut self,
+        entity_id: EntityId,
+        reset: impl FnOnce(&mut C),
+    ) -> Result<(), Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+        if self
+            .get_component_storage::<C>()
+            .is_some_and(|component_storage| component_storage.contains(entity_id))
+        {
+            return Err(Error::ComponentAlreadyAdded(
+                std::any::type_name::<C>(),
+                entity_id,
+            ));
+        }
+
+        let mut component_data = self
+            .get_component_storage_mut::<C>()
+            .and_then(|component_storage| component_storage.pool.as_mut()?.pop())
+            .unwrap_or_default();
+        reset(&mut component_data);
+
+        self.add_valid_entity_component(entity_id, component_data)
+    }
+}
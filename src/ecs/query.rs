@@ -0,0 +1,232 @@
+use std::any::Any;
+
+use super::changes::ChangeKind;
+use super::component_storage::{ComponentsStorage, Storage};
+use super::id_types::{ComponentId, EntityId};
+use super::world::World;
+
+/// The entities of whichever storage is smaller, since a join can't have more than that.
+fn smaller_storage_entities<'w, A: 'static, B: 'static>(
+    a: &'w impl Storage<A>,
+    b: &'w impl Storage<B>,
+) -> impl Iterator<Item = EntityId> + 'w {
+    let driven_by_a = a.len() <= b.len();
+    let a_entities = driven_by_a.then(|| a.iter().map(|(entity_id, _)| entity_id));
+    let b_entities = (!driven_by_a).then(|| b.iter().map(|(entity_id, _)| entity_id));
+
+    a_entities
+        .into_iter()
+        .flatten()
+        .chain(b_entities.into_iter().flatten())
+}
+
+/// The entities that may be in both storages, an exact intersection when both keep bitsets.
+/// Only goes through [`Storage`], so joins work the same whatever the backends are.
+fn joined_entities<'w, A: 'static, B: 'static>(
+    a: &'w impl Storage<A>,
+    b: &'w impl Storage<B>,
+) -> impl Iterator<Item = EntityId> + 'w {
+    let bitsets = a.bitset().zip(b.bitset());
+    let intersection = bitsets.map(|(a_bitset, b_bitset)| a_bitset.intersection(b_bitset));
+    let probed = bitsets.is_none().then(|| smaller_storage_entities(a, b));
+
+    intersection
+        .into_iter()
+        .flatten()
+        .chain(probed.into_iter().flatten())
+}
+
+impl World {
+    /// Every active entity that has both `A` and `B`, in the storage order of the smaller of
+    /// the two, or in index order if both have bitsets enabled.
+    pub fn query2<A: 'static, B: 'static>(&self) -> impl Iterator<Item = (EntityId, &A, &B)> {
+        let storages = self
+            .get_component_storage::<A>()
+            .zip(self.get_component_storage::<B>());
+
+        storages.into_iter().flat_map(|(a, b)| {
+            joined_entities(a, b)
+                .filter(|entity_id| !self.inactive_set.contains(entity_id))
+                .filter_map(move |entity_id| {
+                    Some((entity_id, a.get(entity_id)?, b.get(entity_id)?))
+                })
+        })
+    }
+
+    /// How many entities [`World::query2`] would yield, without building the tuples.
+    pub fn count_query2<A: 'static, B: 'static>(&self) -> usize {
+        let Some((a, b)) = self
+            .get_component_storage::<A>()
+            .zip(self.get_component_storage::<B>())
+        else {
+            return 0;
+        };
+
+        joined_entities(a, b)
+            .filter(|&entity_id| a.contains(entity_id) && b.contains(entity_id))
+            .filter(|entity_id| !self.inactive_set.contains(entity_id))
+            .count()
+    }
+
+    /// Like [`World::query2`] but mutable, in `A`'s storage order.
+    ///
+    /// `A` and `B` must be different types, otherwise both references would point at the same
+    /// component. That's a debug assertion, and an empty iterator in release.
+    pub fn query2_mut<A: 'static, B: 'static>(
+        &mut self,
+    ) -> impl Iterator<Item = (EntityId, &mut A, &mut B)> {
+        let inactive_set = &self.inactive_set;
+        let mut storages =
+            Self::get_two_component_storages_mut::<A, B>(&mut self.component_storage_vecs).ok();
+
+        if let Some((a, b)) = storages.as_mut()
+            && (a.changes.is_some() || b.changes.is_some())
+        {
+            let joined: Vec<EntityId> = joined_entities(*a, *b)
+                .filter(|&entity_id| a.contains(entity_id) && b.contains(entity_id))
+                .filter(|entity_id| !inactive_set.contains(entity_id))
+                .collect();
+            for entity_id in joined {
+                a.record_change(entity_id, ChangeKind::Modified);
+                b.record_change(entity_id, ChangeKind::Modified);
+            }
+        }
+
+        storages.into_iter().flat_map(move |(a, b)| {
+            let b_slots = b.slots_mut();
+            a.iter_mut()
+                .filter(move |(entity_id, _)| !inactive_set.contains(entity_id))
+                .filter_map(move |(entity_id, a)| {
+                    // SAFETY: `A`'s entities are all different, so none is taken twice.
+                    let b = unsafe { b_slots.take(entity_id) }?;
+                    Some((entity_id, a, b))
+                })
+        })
+    }
+
+    /// [`World::query2_mut`] for three different types.
+    pub fn query3_mut<A: 'static, B: 'static, C: 'static>(
+        &mut self,
+    ) -> impl Iterator<Item = (EntityId, &mut A, &mut B, &mut C)> {
+        let inactive_set = &self.inactive_set;
+        let mut storages =
+            Self::get_three_component_storages_mut::<A, B, C>(&mut self.component_storage_vecs)
+                .ok();
+
+        if let Some((a, b, c)) = storages.as_mut()
+            && (a.changes.is_some() || b.changes.is_some() || c.changes.is_some())
+        {
+            let joined: Vec<EntityId> = a
+                .iter()
+                .map(|(entity_id, _)| entity_id)
+                .filter(|&entity_id| b.contains(entity_id) && c.contains(entity_id))
+                .filter(|entity_id| !inactive_set.contains(entity_id))
+                .collect();
+            for entity_id in joined {
+                a.record_change(entity_id, ChangeKind::Modified);
+                b.record_change(entity_id, ChangeKind::Modified);
+                c.record_change(entity_id, ChangeKind::Modified);
+            }
+        }
+
+        storages.into_iter().flat_map(move |(a, b, c)| {
+            let (b_slots, c_slots) = (b.slots_mut(), c.slots_mut());
+            a.iter_mut()
+                .filter(move |(entity_id, _)| !inactive_set.contains(entity_id))
+                .filter_map(move |(entity_id, a)| {
+                    // SAFETY: `A`'s entities are all different, so none is taken twice.
+                    let (b, c) = unsafe { (b_slots.take(entity_id)?, c_slots.take(entity_id)?) };
+                    Some((entity_id, a, b, c))
+                })
+        })
+    }
+
+    /// Like [`World::query2`], also yielding `O` for the entities that happen to have it.
+    pub fn query2_opt<A: 'static, B: 'static, O: 'static>(
+        &self,
+    ) -> impl Iterator<Item = (EntityId, &A, &B, Option<&O>)> {
+        let optional = self.get_component_storage::<O>();
+
+        self.query2::<A, B>().map(move |(entity_id, a, b)| {
+            let o = optional.and_then(|component_storage| component_storage.get(entity_id));
+            (entity_id, a, b, o)
+        })
+    }
+
+    /// Calls `f` once for every unordered pair of active entities that have `C`, for narrow
+    /// phase collision and other interactions. The first of each pair comes earlier in storage.
+    ///
+    /// It's `O(n²)`, meant for small sets, e.g. the entities of one spatial hash bucket.
+    pub fn for_each_pair<C: 'static, F: FnMut(EntityId, &C, EntityId, &C)>(&self, mut f: F) {
+        let Some(component_storage) = self.get_component_storage::<C>() else {
+            return;
+        };
+
+        let components: Vec<(EntityId, &C)> = component_storage
+            .iter()
+            .filter(|(entity_id, _)| !self.inactive_set.contains(entity_id))
+            .collect();
+        for (i, &(a_id, a)) in components.iter().enumerate() {
+            for &(b_id, b) in &components[i + 1..] {
+                f(a_id, a, b_id, b);
+            }
+        }
+    }
+
+    /// Every active entity's `C`, in storage order. Empty if `C` was never registered.
+    pub fn iter_component<C: 'static>(&self) -> impl Iterator<Item = (EntityId, &C)> {
+        self.get_component_storage::<C>()
+            .into_iter()
+            .flat_map(|component_storage| component_storage.iter())
+            .filter(|(entity_id, _)| !self.inactive_set.contains(entity_id))
+    }
+
+    /// [`World::iter_component`] but mutable. Every yielded entity counts as modified for
+    /// change tracking, like with [`World::query2_mut`].
+    pub fn iter_component_mut<C: 'static>(&mut self) -> impl Iterator<Item = (EntityId, &mut C)> {
+        let inactive_set = &self.inactive_set;
+        let mut component_storage = self
+            .component_storage_vecs
+            .get_mut(&ComponentId::of::<C>())
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<C>>());
+
+        if let Some(component_storage) = component_storage.as_deref_mut()
+            && component_storage.changes.is_some()
+        {
+            let active: Vec<EntityId> = component_storage
+                .iter()
+                .map(|(entity_id, _)| entity_id)
+                .filter(|entity_id| !inactive_set.contains(entity_id))
+                .collect();
+            for entity_id in active {
+                component_storage.record_change(entity_id, ChangeKind::Modified);
+            }
+        }
+
+        component_storage
+            .into_iter()
+            .flat_map(|component_storage| component_storage.iter_mut())
+            .filter(move |(entity_id, _)| !inactive_set.contains(entity_id))
+    }
+
+    /// The `C`s of active entities sorted by `key`, ties keep their storage order.
+    ///
+    /// It collects and sorts on every call, `O(n log n)`, so it's not meant for hot loops
+    /// over big storages.
+    pub fn iter_components_sorted_by<C: 'static, K: Ord, F: Fn(&C) -> K>(
+        &self,
+        key: F,
+    ) -> Vec<(EntityId, &C)> {
+        let Some(component_storage) = self.get_component_storage::<C>() else {
+            return Vec::new();
+        };
+
+        let mut components: Vec<(EntityId, &C)> = component_storage
+            .components
+            .iter()
+            .filter(|(entity_id, _)| !self.inactive_set.contains(entity_id))
+            .collect();
+        components.sort_by_key(|(_, component)| key(component));
+        components
+    }
+}
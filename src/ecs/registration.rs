@@ -0,0 +1,77 @@
+use std::marker::PhantomData;
+
+use super::world::World;
+
+/// Chains the optional per-type setup after [`World::register`], each method is the same as
+/// the `World` method it's named after and only exists when `C` has the traits it needs.
+pub struct ComponentRegistration<'w, C> {
+    world: &'w mut World,
+    component: PhantomData<C>,
+}
+
+impl World {
+    /// The one entry point for setting up `C`: creates its storage right away, then the
+    /// returned [`ComponentRegistration`] attaches whatever else the type supports, e.g.
+    /// `world.register::<Health>().clonable().snapshottable()`.
+    ///
+    /// Registering isn't required, the first add of a `C` would create the storage too, but
+    /// doing it up front means reads before any insert fail with
+    /// [`Error::InvalidEntityComponent`](super::Error::InvalidEntityComponent), not
+    /// [`Error::InvalidWorldComponent`](super::Error::InvalidWorldComponent). Registering
+    /// again after inserts is fine, the storage and its components are kept.
+    pub fn register<C: 'static>(&mut self) -> ComponentRegistration<'_, C> {
+        self.ensure_component_registered::<C>();
+        ComponentRegistration {
+            world: self,
+            component: PhantomData,
+        }
+    }
+}
+
+impl<C: 'static> ComponentRegistration<'_, C> {
+    pub fn validator(self, validator: impl Fn(&C) -> Result<(), String> + 'static) -> Self {
+        self.world.register_validator::<C>(validator);
+        self
+    }
+
+    pub fn track_changes(self) -> Self {
+        self.world.track_changes::<C>();
+        self
+    }
+
+    pub fn bitset(self) -> Self {
+        self.world.enable_bitset::<C>();
+        self
+    }
+
+    pub fn soa(self) -> Self {
+        self.world.enable_soa::<C>();
+        self
+    }
+}
+
+impl<C: Clone + 'static> ComponentRegistration<'_, C> {
+    pub fn clonable(self) -> Self {
+        self.world.register_clonable::<C>();
+        self
+    }
+
+    pub fn snapshottable(self) -> Self {
+        self.world.register_snapshottable::<C>();
+        self
+    }
+}
+
+impl<C: std::fmt::Debug + 'static> ComponentRegistration<'_, C> {
+    pub fn debuggable(self) -> Self {
+        self.world.register_debuggable::<C>();
+        self
+    }
+}
+
+impl<C: Default + 'static> ComponentRegistration<'_, C> {
+    pub fn pooled(self) -> Self {
+        self.world.register_pooled::<C>();
+        self
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+use super::component_storage::{AnyComponentsStorage, StorageCloneFn, clone_storage};
+use super::error::Error;
+use super::hashing::EcsHashSet;
+use super::hierarchy::is_hierarchy_component;
+use super::id_types::{ComponentId, EntityId};
+use super::world::World;
+
+/// Clones the first entity's `C`, if it has one, onto the second.
+pub(super) type EntityCloneFn = fn(&mut World, EntityId, EntityId) -> Result<(), Error>;
+
+fn clone_entity_component<C: Clone + 'static>(
+    world: &mut World,
+    src: EntityId,
+    dst: EntityId,
+) -> Result<(), Error> {
+    let Some(component_data) = world.get_entity_component_lenient::<C>(src)?.cloned() else {
+        return Ok(());
+    };
+    world.add_entity_component(dst, component_data)
+}
+
+/// What [`World::deep_clone_with`] and [`World::clone_entity_with`] do with components that
+/// weren't registered as clonable.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum UnclonablePolicy {
+    /// The clone simply won't have them.
+    #[default]
+    Skip,
+    Panic,
+}
+
+/// An in-memory copy of a world, see [`World::snapshot`].
+///
+/// Storages are copied as they are, so after a restore components iterate in the exact order
+/// they did when the snapshot was taken, which deterministic systems rely on.
+pub struct WorldSnapshot {
+    component_storages: HashMap<ComponentId, (Box<dyn AnyComponentsStorage>, StorageCloneFn)>,
+    entity_validity_set: EcsHashSet<EntityId>,
+    inactive_set: EcsHashSet<EntityId>,
+    entity_counter: usize,
+    entity_generation: u32,
+    free_indices: Vec<(usize, u32)>,
+}
+
+impl World {
+    /// Makes `C` part of every [`WorldSnapshot`] taken from now on.
+    pub fn register_snapshottable<C: Clone + 'static>(&mut self) {
+        self.ensure_component_registered::<C>();
+        self.snapshot_shims
+            .insert(ComponentId::of::<C>(), clone_storage::<C>);
+    }
+
+    /// Whether [`World::register_snapshottable`] was called for `C`.
+    pub fn is_snapshottable<C: 'static>(&self) -> bool {
+        self.snapshot_shims.contains_key(&ComponentId::of::<C>())
+    }
+
+    /// Copies the entities and every component registered with
+    /// [`World::register_snapshottable`], other components are not captured.
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let component_storages = self
+            .snapshot_shims
+            .iter()
+            .filter_map(|(component_id, &clone_fn)| {
+                let component_storage = self.component_storage_vecs.get(component_id)?;
+                Some((
+                    *component_id,
+                    (clone_fn(component_storage.as_ref()), clone_fn),
+                ))
+            })
+            .collect();
+
+        WorldSnapshot {
+            component_storages,
+            entity_validity_set: self.entity_validity_set.clone(),
+            inactive_set: self.inactive_set.clone(),
+            entity_counter: self.entity_counter.load(Ordering::Relaxed),
+            entity_generation: self.entity_generation,
+            free_indices: self.free_indices.clone(),
+        }
+    }
+
+    /// Brings the world back to exactly how it was when `snapshot` was taken.
+    ///
+    /// Components that aren't snapshottable are kept as they are now, except for
+    /// entities that didn't exist back then, which lose them. The hierarchy is always
+    /// snapshottable, and names are looked up in the restored [`Name`](super::Name) storage.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        self.entity_validity_set = snapshot.entity_validity_set.clone();
+        self.reindex_live_entities();
+        self.inactive_set = snapshot.inactive_set.clone();
+        *self.entity_counter.get_mut() = snapshot.entity_counter;
+        self.entity_generation = snapshot.entity_generation;
+        self.free_indices = snapshot.free_indices.clone();
+
+        for (component_id, (component_storage, clone_fn)) in &snapshot.component_storages {
+            self.component_storage_vecs
+                .insert(*component_id, clone_fn(component_storage.as_ref()));
+            self.snapshot_shims.insert(*component_id, *clone_fn);
+        }
+
+        let entity_validity_set = &self.entity_validity_set;
+        for (component_id, component_storage) in self.component_storage_vecs.iter_mut() {
+            if !snapshot.component_storages.contains_key(component_id) {
+                component_storage
+                    .retain_entities(&mut |entity_id| entity_validity_set.contains(&entity_id));
+            }
+        }
+
+        self.prune_hierarchy();
+        self.reindex_names();
+    }
+
+    /// Makes `C` part of the world returned by [`World::deep_clone`].
+    pub fn register_clonable<C: Clone + 'static>(&mut self) {
+        self.ensure_component_registered::<C>();
+        self.clone_shims
+            .insert(ComponentId::of::<C>(), clone_storage::<C>);
+        self.entity_clone_shims
+            .insert(ComponentId::of::<C>(), clone_entity_component::<C>);
+    }
+
+    /// Whether [`World::register_clonable`] was called for `C`.
+    pub fn is_clonable<C: 'static>(&self) -> bool {
+        self.clone_shims.contains_key(&ComponentId::of::<C>())
+    }
+
+    /// Same as [`World::clone_entity_with`] with [`UnclonablePolicy::Skip`].
+    pub fn clone_entity(&mut self, src: EntityId) -> Result<EntityId, Error> {
+        self.clone_entity_with(src, UnclonablePolicy::Skip)
+    }
+
+    /// A fresh entity with copies of all of `src`'s components that were registered with
+    /// [`World::register_clonable`], e.g. to instance a prefab. Hierarchy components are left
+    /// out, so the copy starts out as a root.
+    ///
+    /// If a component can't be added, say a validator rejects it, the copy is despawned again.
+    pub fn clone_entity_with(
+        &mut self,
+        src: EntityId,
+        policy: UnclonablePolicy,
+    ) -> Result<EntityId, Error> {
+        if !self.is_entity_valid(src) {
+            return Err(Error::InvalidEntityId(src));
+        }
+
+        if policy == UnclonablePolicy::Panic
+            && let Some(component_storage) =
+                self.component_storage_vecs
+                    .iter()
+                    .find_map(|(component_id, component_storage)| {
+                        (component_storage.contains_entity(src)
+                            && !self.entity_clone_shims.contains_key(component_id)
+                            && !is_hierarchy_component(*component_id))
+                        .then_some(component_storage)
+                    })
+        {
+            panic!(
+                "Component {} was never registered as clonable",
+                component_storage.component_type_name()
+            );
+        }
+
+        let dst = self.try_create_entity()?;
+        let clone_fns: Vec<EntityCloneFn> = self.entity_clone_shims.values().copied().collect();
+        for clone_fn in clone_fns {
+            if let Err(error) = clone_fn(self, src, dst) {
+                self.despawn_entity(dst)
+                    .expect("The entity was just created");
+                return Err(error);
+            }
+        }
+
+        Ok(dst)
+    }
+
+    /// Same as [`World::deep_clone_with`] with [`UnclonablePolicy::Skip`].
+    pub fn deep_clone(&self) -> World {
+        self.deep_clone_with(UnclonablePolicy::Skip)
+    }
+
+    /// An independent copy of the world, with all the components that were registered
+    /// with [`World::register_clonable`].
+    pub fn deep_clone_with(&self, policy: UnclonablePolicy) -> World {
+        let mut world = World::new();
+
+        for (component_id, component_storage) in &self.component_storage_vecs {
+            match self.clone_shims.get(component_id) {
+                Some(clone_fn) => {
+                    world
+                        .component_storage_vecs
+                        .insert(*component_id, clone_fn(component_storage.as_ref()));
+                }
+                None if policy == UnclonablePolicy::Panic => panic!(
+                    "Component {} was never registered as clonable",
+                    component_storage.component_type_name()
+                ),
+                None => {}
+            }
+        }
+
+        world.entity_validity_set = self.entity_validity_set.clone();
+        world.live_indices = self.live_indices.clone();
+        world.inactive_set = self.inactive_set.clone();
+        *world.entity_counter.get_mut() = self.entity_counter.load(Ordering::Relaxed);
+        world.snapshot_shims = self.snapshot_shims.clone();
+        world.clone_shims = self.clone_shims.clone();
+        world.entity_clone_shims = self.entity_clone_shims.clone();
+        world.dense_capacity = self.dense_capacity;
+        world.entity_generation = self.entity_generation;
+        world.free_indices = self.free_indices.clone();
+        world.debug_shims = self.debug_shims.clone();
+        world.name_index = self.name_index.clone();
+        world
+    }
+}
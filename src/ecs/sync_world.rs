@@ -0,0 +1,210 @@
+use std::any::Any;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::component_storage::{AnyComponentsStorage, ComponentsStorage, Storage};
+use super::error::Error;
+use super::id_types::{ComponentId, EntityId};
+
+type SyncComponentsStorage = Box<dyn AnyComponentsStorage + Send + Sync>;
+
+/// A world that can be shared between threads, every component type is behind its own
+/// `RwLock` so systems touching different components can run at the same time.
+///
+/// Components must be `Send + Sync`, and have to be registered with
+/// [`SyncWorld::register`] while the world is still exclusively owned, before sharing it.
+///
+/// Taking guards for several component types in different orders on different threads
+/// can deadlock, same as with any other set of locks.
+pub struct SyncWorld {
+    component_storage_vecs: HashMap<ComponentId, RwLock<SyncComponentsStorage>>,
+    entity_validity_set: RwLock<HashSet<EntityId>>,
+    entity_counter: AtomicUsize,
+}
+
+impl Default for SyncWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncWorld {
+    pub fn new() -> Self {
+        Self {
+            component_storage_vecs: HashMap::new(),
+            entity_validity_set: RwLock::new(HashSet::new()),
+            entity_counter: 0.into(),
+        }
+    }
+
+    /// Returns `true` if the component was already registered.
+    pub fn register<C: Send + Sync + 'static>(&mut self) -> bool {
+        if let Entry::Vacant(entry) = self.component_storage_vecs.entry(ComponentId::of::<C>()) {
+            entry.insert(RwLock::new(Box::new(ComponentsStorage::<C>::new())));
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn create_entity(&self) -> EntityId {
+        let entity_id = EntityId::from_raw(self.entity_counter.fetch_add(1, Ordering::Relaxed));
+        self.entity_validity_set
+            .write()
+            .expect("A thread panicked while holding the entities lock")
+            .insert(entity_id);
+        entity_id
+    }
+
+    pub fn is_entity_valid(&self, entity_id: EntityId) -> bool {
+        self.entity_validity_set
+            .read()
+            .expect("A thread panicked while holding the entities lock")
+            .contains(&entity_id)
+    }
+
+    /// Takes the write lock of every component type, so no guards may be held by the
+    /// calling thread.
+    pub fn despawn_entity(&self, entity_id: EntityId) -> Result<(), Error> {
+        if !self
+            .entity_validity_set
+            .write()
+            .expect("A thread panicked while holding the entities lock")
+            .remove(&entity_id)
+        {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        for component_storage in self.component_storage_vecs.values() {
+            component_storage
+                .write()
+                .expect("A thread panicked while holding a component lock")
+                .remove_entity(entity_id);
+        }
+
+        Ok(())
+    }
+
+    /// Shared access to all `C` components, blocks while someone writes them.
+    pub fn read_component<C: Send + Sync + 'static>(
+        &self,
+    ) -> Result<ComponentReadGuard<'_, C>, Error> {
+        let guard = self
+            .component_storage_vecs
+            .get(&ComponentId::of::<C>())
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?
+            .read()
+            .expect("A thread panicked while holding a component lock");
+
+        Ok(ComponentReadGuard {
+            guard,
+            component: PhantomData,
+        })
+    }
+
+    /// Exclusive access to all `C` components, blocks while someone reads or writes them.
+    pub fn write_component<C: Send + Sync + 'static>(
+        &self,
+    ) -> Result<ComponentWriteGuard<'_, C>, Error> {
+        let guard = self
+            .component_storage_vecs
+            .get(&ComponentId::of::<C>())
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?
+            .write()
+            .expect("A thread panicked while holding a component lock");
+
+        Ok(ComponentWriteGuard {
+            guard,
+            entity_validity_set: &self.entity_validity_set,
+            component: PhantomData,
+        })
+    }
+}
+
+pub struct ComponentReadGuard<'w, C> {
+    guard: RwLockReadGuard<'w, SyncComponentsStorage>,
+    component: PhantomData<C>,
+}
+
+impl<C: 'static> ComponentReadGuard<'_, C> {
+    fn storage(&self) -> &ComponentsStorage<C> {
+        (self.guard.as_ref() as &dyn Any)
+            .downcast_ref::<ComponentsStorage<C>>()
+            .expect("The storage is registered under the component's own id")
+    }
+
+    pub fn get(&self, entity_id: EntityId) -> Option<&C> {
+        self.storage().get(entity_id)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (EntityId, &C)> + ExactSizeIterator {
+        self.storage().components.iter()
+    }
+}
+
+pub struct ComponentWriteGuard<'w, C> {
+    guard: RwLockWriteGuard<'w, SyncComponentsStorage>,
+    entity_validity_set: &'w RwLock<HashSet<EntityId>>,
+    component: PhantomData<C>,
+}
+
+impl<C: 'static> ComponentWriteGuard<'_, C> {
+    fn storage(&self) -> &ComponentsStorage<C> {
+        (self.guard.as_ref() as &dyn Any)
+            .downcast_ref::<ComponentsStorage<C>>()
+            .expect("The storage is registered under the component's own id")
+    }
+
+    fn storage_mut(&mut self) -> &mut ComponentsStorage<C> {
+        (self.guard.as_mut() as &mut dyn Any)
+            .downcast_mut::<ComponentsStorage<C>>()
+            .expect("The storage is registered under the component's own id")
+    }
+
+    pub fn get(&self, entity_id: EntityId) -> Option<&C> {
+        self.storage().get(entity_id)
+    }
+
+    pub fn get_mut(&mut self, entity_id: EntityId) -> Option<&mut C> {
+        self.storage_mut().get_mut(entity_id)
+    }
+
+    pub fn iter_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = (EntityId, &mut C)> + ExactSizeIterator {
+        self.storage_mut().components.iter_mut()
+    }
+
+    pub fn insert(&mut self, entity_id: EntityId, component_data: C) -> Result<(), Error> {
+        if !self
+            .entity_validity_set
+            .read()
+            .expect("A thread panicked while holding the entities lock")
+            .contains(&entity_id)
+        {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        if self.storage().contains(entity_id) {
+            return Err(Error::ComponentAlreadyAdded(
+                std::any::type_name::<C>(),
+                entity_id,
+            ));
+        }
+
+        self.storage_mut().insert(entity_id, component_data);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, entity_id: EntityId) -> Result<C, Error> {
+        self.storage_mut()
+            .remove(entity_id)
+            .ok_or(Error::InvalidEntityComponent(
+                std::any::type_name::<C>(),
+                entity_id,
+            ))
+    }
+}
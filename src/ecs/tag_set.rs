@@ -0,0 +1,148 @@
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+use super::bitset::EntityBitset;
+use super::id_types::EntityId;
+
+/// The storage of a zero-sized component, a tag. There's nothing to keep but which entities
+/// have it, so it's only a bitset, and every entity lends out the same dangling reference.
+/// That's a bit and a generation per entity index however many tags there are, instead of an
+/// id and a map entry per tag.
+///
+/// Tags are forgotten when they're added and made up again when they're removed, the way
+/// `Vec` handles zero-sized types, so every value that goes in is dropped exactly once.
+pub(super) struct TagSet<C> {
+    entities: EntityBitset,
+    len: usize,
+    tags: PhantomData<C>,
+}
+
+impl<C> TagSet<C> {
+    pub(super) fn new() -> Self {
+        assert_eq!(
+            0,
+            std::mem::size_of::<C>(),
+            "Only zero-sized types are tags"
+        );
+        Self {
+            entities: EntityBitset::default(),
+            len: 0,
+            tags: PhantomData,
+        }
+    }
+
+    /// The one instance every entity shares.
+    pub(super) fn tag<'a>() -> &'a C {
+        // SAFETY: `C` is zero-sized, so a dangling pointer, which is non-null and aligned, is
+        // valid for it. `new` makes sure no other `C` gets here.
+        unsafe { NonNull::<C>::dangling().as_ref() }
+    }
+
+    /// Like [`Self::tag`], references to a zero-sized value cover no memory so they can't
+    /// alias each other.
+    pub(super) fn tag_mut<'a>() -> &'a mut C {
+        // SAFETY: See `tag`.
+        unsafe { NonNull::<C>::dangling().as_mut() }
+    }
+
+    /// Gives back ownership of one of the tags forgotten by [`Self::insert`].
+    fn make_tag() -> C {
+        // SAFETY: See `tag`, reading a zero-sized value touches no memory. A tag was forgotten
+        // for every one made up here, so no value is dropped twice.
+        unsafe { NonNull::<C>::dangling().read() }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Tags sit at their entity's index, so this is one past the highest one.
+    pub(super) fn slot_count(&self) -> usize {
+        self.entities.index_count()
+    }
+
+    pub(super) fn contains(&self, entity_id: EntityId) -> bool {
+        self.entities.contains(entity_id)
+    }
+
+    pub(super) fn entity_at(&self, index: usize) -> Option<EntityId> {
+        self.entities.entity_at(index)
+    }
+
+    /// The entities that have the tag, in index order.
+    pub(super) fn entity_ids(&self) -> impl DoubleEndedIterator<Item = EntityId> + '_ {
+        (0..self.slot_count()).filter_map(|index| self.entity_at(index))
+    }
+
+    pub(super) fn bitset(&self) -> &EntityBitset {
+        &self.entities
+    }
+
+    /// The entity must not already have the tag.
+    pub(super) fn insert(&mut self, entity_id: EntityId, tag: C) {
+        debug_assert!(!self.contains(entity_id));
+        self.entities.insert(entity_id);
+        std::mem::forget(tag);
+        self.len += 1;
+    }
+
+    /// Takes the tag of the entity at `index`, which must have one.
+    pub(super) fn remove(&mut self, index: usize) -> C {
+        let entity_id = self.entity_at(index).expect("Mapped slots are set");
+        self.entities.remove(entity_id);
+        self.len -= 1;
+        Self::make_tag()
+    }
+
+    /// Takes every tag out, in index order.
+    pub(super) fn take_all(&mut self) -> Vec<(EntityId, C)> {
+        let tags = self
+            .entity_ids()
+            .map(|entity_id| (entity_id, Self::make_tag()))
+            .collect();
+        self.entities.clear();
+        self.len = 0;
+        tags
+    }
+
+    pub(super) fn clear(&mut self) {
+        if std::mem::needs_drop::<C>() {
+            self.take_all();
+        } else {
+            self.entities.clear();
+            self.len = 0;
+        }
+    }
+
+    pub(super) fn remap_entity_ids(&mut self, mut remap: impl FnMut(EntityId) -> EntityId) {
+        let entity_ids: Vec<EntityId> = self.entity_ids().collect();
+        self.entities.clear();
+        for entity_id in entity_ids {
+            self.entities.insert(remap(entity_id));
+        }
+    }
+
+    pub(super) fn memory_usage(&self) -> usize {
+        self.entities.memory_usage()
+    }
+}
+
+impl<C: Clone> Clone for TagSet<C> {
+    /// Clones the shared instance once per tag, so `Clone` runs as often as for any component.
+    fn clone(&self) -> Self {
+        for _ in 0..self.len {
+            std::mem::forget(Self::tag().clone());
+        }
+        Self {
+            entities: self.entities.clone(),
+            len: self.len,
+            tags: PhantomData,
+        }
+    }
+}
+
+impl<C> Drop for TagSet<C> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
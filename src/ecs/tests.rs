@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use super::*; // Import items from the parent module
 
 struct PositionComponent([i32; 3]);
@@ -156,3 +158,4043 @@ fn single_entity_component_sanity() {
             .0
     );
 }
+
+#[test]
+fn swap_components_between_entities() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+
+    world
+        .add_entity_component(a, PositionComponent([1, 1, 1]))
+        .unwrap();
+    world
+        .add_entity_component(b, PositionComponent([2, 2, 2]))
+        .unwrap();
+
+    world.swap_components::<PositionComponent>(a, b).unwrap();
+
+    assert_eq!(
+        [2, 2, 2],
+        world
+            .get_entity_component::<PositionComponent>(a)
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        [1, 1, 1],
+        world
+            .get_entity_component::<PositionComponent>(b)
+            .unwrap()
+            .0
+    );
+
+    // `c` has no position, so it's the one reported.
+    assert!(matches!(
+        world.swap_components::<PositionComponent>(a, c),
+        Err(Error::InvalidEntityComponent(_, id)) if id == c
+    ));
+}
+
+#[test]
+fn entities_with_follows_insertion_order() {
+    let mut world = World::new();
+
+    assert!(world.entities_with::<HealthComponent>().is_empty());
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+
+    world.add_entity_component(c, HealthComponent(3)).unwrap();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, PlayerTag).unwrap();
+
+    let entities = world.entities_with::<HealthComponent>();
+    assert_eq!(vec![c, a], entities);
+
+    // It's a snapshot, so the world can be mutated while holding it.
+    for entity_id in entities {
+        world
+            .remove_entity_component::<HealthComponent>(entity_id)
+            .unwrap();
+    }
+    assert!(world.entities_with::<HealthComponent>().is_empty());
+}
+
+#[test]
+fn find_entity_by_health_threshold() {
+    let mut world = World::new();
+
+    assert_eq!(None, world.find_entity::<HealthComponent, _>(|_| true));
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+
+    world.add_entity_component(a, HealthComponent(10)).unwrap();
+    world.add_entity_component(b, HealthComponent(80)).unwrap();
+    world.add_entity_component(c, HealthComponent(90)).unwrap();
+
+    assert_eq!(
+        Some(b),
+        world.find_entity::<HealthComponent, _>(|health| health.0 > 50)
+    );
+    assert_eq!(
+        None,
+        world.find_entity::<HealthComponent, _>(|health| health.0 > 100)
+    );
+}
+
+#[test]
+fn world_default_and_take() {
+    let mut world = World::default();
+    let player_id = world.create_entity();
+    world.add_entity_component(player_id, PlayerTag).unwrap();
+
+    let taken = std::mem::take(&mut world);
+    assert!(taken.is_entity_valid(player_id));
+    assert!(!world.is_entity_valid(player_id));
+
+    assert_eq!(
+        ComponentStorageType::SparseSet,
+        ComponentStorageType::default()
+    );
+}
+
+#[test]
+fn world_len_and_is_empty() {
+    let mut world = World::new();
+    assert!(world.is_empty());
+    assert_eq!(0, world.len());
+
+    world.ensure_component_registered::<HealthComponent>();
+    assert!(world.is_empty(), "Storages aren't entities.");
+
+    world.create_entity();
+    world.create_entity();
+    assert!(!world.is_empty());
+    assert_eq!(2, world.len());
+}
+
+#[test]
+fn entity_ref_reads_many_components() {
+    let mut world = World::new();
+
+    let player_id = world.create_entity();
+    world
+        .add_entity_component(player_id, HealthComponent(100))
+        .unwrap();
+    world
+        .add_entity_component(player_id, PositionComponent([1, 2, 3]))
+        .unwrap();
+
+    let player = world.entity(player_id).unwrap();
+    assert_eq!(player_id, player.id());
+    assert_eq!(100, player.get::<HealthComponent>().unwrap().0);
+    assert_eq!([1, 2, 3], player.get::<PositionComponent>().unwrap().0);
+    assert!(!player.has::<PlayerTag>());
+    assert!(player.get::<PlayerTag>().is_none());
+
+    let other_world = World::new();
+    assert!(other_world.entity(player_id).is_err());
+}
+
+#[test]
+fn entity_mut_builds_and_despawns() {
+    let mut world = World::new();
+
+    let player_id = world.create_entity();
+    let mut player = world.entity_mut(player_id).unwrap();
+    player
+        .insert(PositionComponent([4, 5, 6]))
+        .insert(HealthComponent(50))
+        .insert(PlayerTag);
+
+    assert!(player.try_insert(PlayerTag).is_err());
+    assert_eq!([4, 5, 6], player.get::<PositionComponent>().unwrap().0);
+
+    player.get_mut::<HealthComponent>().unwrap().0 += 1;
+    assert_eq!(51, player.get::<HealthComponent>().unwrap().0);
+
+    player.despawn();
+
+    assert!(!world.is_entity_valid(player_id));
+    assert!(matches!(
+        world.get_entity_component::<HealthComponent>(player_id),
+        Err(Error::InvalidEntityId(_))
+    ));
+    assert!(world.entities_with::<PositionComponent>().is_empty());
+}
+
+#[test]
+fn despawn_entity_keeps_other_components() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+
+    world.despawn_entity(a).unwrap();
+    assert!(world.despawn_entity(a).is_err());
+
+    assert_eq!(
+        2,
+        world.get_entity_component::<HealthComponent>(b).unwrap().0
+    );
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct VelocityComponent([i32; 3]);
+
+#[test]
+fn snapshot_and_restore() {
+    let mut world = World::new();
+    world.register_snapshottable::<VelocityComponent>();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world
+        .add_entity_component(a, VelocityComponent([1, 0, 0]))
+        .unwrap();
+    world
+        .add_entity_component(b, VelocityComponent([0, 1, 0]))
+        .unwrap();
+    world.add_entity_component(b, PlayerTag).unwrap();
+
+    let snapshot = world.snapshot();
+
+    world
+        .get_entity_component_mut::<VelocityComponent>(a)
+        .unwrap()
+        .0 = [9, 9, 9];
+    world
+        .remove_entity_component::<VelocityComponent>(b)
+        .unwrap();
+    let c = world.create_entity();
+    world
+        .add_entity_component(c, VelocityComponent([0, 0, 1]))
+        .unwrap();
+    world.add_entity_component(c, PlayerTag).unwrap();
+
+    world.restore(&snapshot);
+
+    assert_eq!(
+        &VelocityComponent([1, 0, 0]),
+        world.get_entity_component::<VelocityComponent>(a).unwrap()
+    );
+    assert_eq!(
+        &VelocityComponent([0, 1, 0]),
+        world.get_entity_component::<VelocityComponent>(b).unwrap()
+    );
+    assert!(!world.is_entity_valid(c));
+    // Not snapshottable, but `c` didn't exist so its tag is gone too.
+    assert_eq!(vec![b], world.entities_with::<PlayerTag>());
+    // The counter is restored, so `c`'s id comes back.
+    assert_eq!(c, world.create_entity());
+}
+
+#[test]
+fn deep_clone_is_independent() {
+    let mut world = World::new();
+    world.register_clonable::<VelocityComponent>();
+
+    let a = world.create_entity();
+    world
+        .add_entity_component(a, VelocityComponent([1, 2, 3]))
+        .unwrap();
+    world.add_entity_component(a, PlayerTag).unwrap();
+
+    let mut clone = world.deep_clone();
+    clone
+        .get_entity_component_mut::<VelocityComponent>(a)
+        .unwrap()
+        .0 = [0, 0, 0];
+
+    assert_eq!(
+        [1, 2, 3],
+        world
+            .get_entity_component::<VelocityComponent>(a)
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        [0, 0, 0],
+        clone
+            .get_entity_component::<VelocityComponent>(a)
+            .unwrap()
+            .0
+    );
+    // `PlayerTag` wasn't clonable, so it got skipped.
+    assert!(clone.get_entity_component::<PlayerTag>(a).is_err());
+    assert_eq!(world.create_entity(), clone.create_entity());
+}
+
+#[test]
+#[should_panic]
+fn deep_clone_panics_on_unclonable() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    world.add_entity_component(a, PlayerTag).unwrap();
+
+    world.deep_clone_with(UnclonablePolicy::Panic);
+}
+
+#[test]
+fn merge_worlds_remaps_ids() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+
+    let mut prefab = World::new();
+    let prefab_a = prefab.create_entity();
+    let prefab_b = prefab.create_entity();
+    prefab
+        .add_entity_component(prefab_a, HealthComponent(10))
+        .unwrap();
+    prefab
+        .add_entity_component(prefab_b, HealthComponent(20))
+        .unwrap();
+    prefab.add_entity_component(prefab_b, PlayerTag).unwrap();
+
+    let mut registry = TypeRegistry::new();
+    registry.register::<HealthComponent>();
+    assert!(registry.contains::<HealthComponent>());
+    assert!(!registry.contains::<PlayerTag>());
+
+    let id_map = world.merge(prefab, &registry);
+
+    assert_eq!(2, id_map.len());
+    assert!(!id_map.values().any(|&new_id| new_id == a));
+    assert_eq!(3, world.len());
+
+    assert_eq!(
+        1,
+        world.get_entity_component::<HealthComponent>(a).unwrap().0
+    );
+    assert_eq!(
+        10,
+        world
+            .get_entity_component::<HealthComponent>(id_map[&prefab_a])
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        20,
+        world
+            .get_entity_component::<HealthComponent>(id_map[&prefab_b])
+            .unwrap()
+            .0
+    );
+    // Not in the registry.
+    assert!(world.entities_with::<PlayerTag>().is_empty());
+}
+
+#[test]
+fn reparenting_updates_both_sides() {
+    let mut world = World::new();
+
+    let old_parent = world.create_entity();
+    let new_parent = world.create_entity();
+    let child = world.create_entity();
+
+    world.set_parent(child, old_parent).unwrap();
+    assert_eq!(Some(old_parent), world.parent_of(child));
+    assert_eq!(
+        &[child],
+        world
+            .get_entity_component::<Children>(old_parent)
+            .unwrap()
+            .ids()
+    );
+
+    world.set_parent(child, new_parent).unwrap();
+    assert_eq!(Some(new_parent), world.parent_of(child));
+    assert!(world.get_entity_component::<Children>(old_parent).is_err());
+    assert_eq!(
+        &[child],
+        world
+            .get_entity_component::<Children>(new_parent)
+            .unwrap()
+            .ids()
+    );
+
+    assert!(matches!(
+        world.set_parent(new_parent, child),
+        Err(Error::HierarchyCycle(_, _))
+    ));
+    assert!(world.set_parent(child, child).is_err());
+}
+
+#[test]
+fn despawn_recursive_removes_subtree() {
+    let mut world = World::new();
+
+    let root = world.create_entity();
+    let child = world.create_entity();
+    let grandchild = world.create_entity();
+    let sibling = world.create_entity();
+    let other_root = world.create_entity();
+
+    world.set_parent(child, root).unwrap();
+    world.set_parent(grandchild, child).unwrap();
+    world.set_parent(sibling, root).unwrap();
+    world.set_parent(root, other_root).unwrap();
+
+    world.despawn_recursive(root).unwrap();
+
+    for entity_id in [root, child, grandchild, sibling] {
+        assert!(!world.is_entity_valid(entity_id));
+    }
+    assert!(world.is_entity_valid(other_root));
+    assert!(world.get_entity_component::<Children>(other_root).is_err());
+}
+
+#[test]
+fn despawn_entity_orphans_children() {
+    let mut world = World::new();
+
+    let parent = world.create_entity();
+    let child = world.create_entity();
+    world.set_parent(child, parent).unwrap();
+
+    world.despawn_entity(parent).unwrap();
+
+    assert!(world.is_entity_valid(child));
+    assert_eq!(None, world.parent_of(child));
+}
+
+#[test]
+fn find_by_name_last_writer_wins() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+
+    assert_eq!(None, world.find_by_name("goblin"));
+
+    world
+        .add_entity_component(a, Name("goblin".to_string()))
+        .unwrap();
+    assert_eq!(Some(a), world.find_by_name("goblin"));
+
+    world
+        .add_entity_component(b, Name("goblin".to_string()))
+        .unwrap();
+    assert_eq!(Some(b), world.find_by_name("goblin"));
+
+    // The newest goblin is gone, the older one is still named that way.
+    world.remove_entity_component::<Name>(b).unwrap();
+    assert_eq!(Some(a), world.find_by_name("goblin"));
+
+    world.despawn_entity(a).unwrap();
+    assert_eq!(None, world.find_by_name("goblin"));
+    assert!(world.name_index.is_empty());
+}
+
+#[test]
+fn find_by_name_falls_back_to_earlier_holders() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+    world
+        .add_component_to_many(&[a, b], Name("orc".to_string()))
+        .unwrap();
+    world
+        .add_entity_component(c, Name("orc".to_string()))
+        .unwrap();
+    assert_eq!(Some(c), world.find_by_name("orc"));
+
+    // Renaming the newest moves the lookup to the one named before it.
+    world
+        .replace_entity_component(c, Name("troll".to_string()))
+        .unwrap();
+    assert_eq!(Some(b), world.find_by_name("orc"));
+    assert_eq!(Some(c), world.find_by_name("troll"));
+
+    // Given the name again, it's the newest holder.
+    world
+        .replace_entity_component(a, Name("orc".to_string()))
+        .unwrap();
+    assert_eq!(Some(a), world.find_by_name("orc"));
+
+    world.despawn_entity(a).unwrap();
+    assert_eq!(Some(b), world.find_by_name("orc"));
+    world.strip_entity(b).unwrap();
+    assert_eq!(None, world.find_by_name("orc"));
+
+    // The index is the only thing consulted, nothing scans the storage on a miss.
+    world.name_index.remove("troll");
+    assert_eq!(None, world.find_by_name("troll"));
+
+    world.clear_component::<Name>();
+    assert!(world.name_index.is_empty());
+}
+
+#[test]
+fn errors_compare_by_value() {
+    let mut world = World::new();
+
+    let player_id = world.create_entity();
+    world.add_entity_component(player_id, PlayerTag).unwrap();
+
+    let error = world
+        .add_entity_component(player_id, PlayerTag)
+        .unwrap_err();
+    assert_eq!(
+        Error::ComponentAlreadyAdded(std::any::type_name::<PlayerTag>(), player_id),
+        error
+    );
+    assert_eq!(error.clone(), error);
+    assert_ne!(Error::InvalidEntityId(player_id), error);
+}
+
+#[test]
+fn error_predicates() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    let b = world.create_entity();
+
+    // (error, is_invalid_entity, is_missing_component, entity_id)
+    let cases = [
+        (Error::InvalidEntityId(a), true, false, Some(a)),
+        (Error::InvalidWorldComponent("Health"), false, true, None),
+        (
+            Error::InvalidEntityComponent("Health", a),
+            false,
+            true,
+            Some(a),
+        ),
+        (
+            Error::ComponentAlreadyAdded("Health", a),
+            false,
+            false,
+            Some(a),
+        ),
+        (Error::HierarchyCycle(a, b), false, false, Some(a)),
+        (Error::DuplicateEntityId(b), false, false, Some(b)),
+        (Error::EntityAlreadyExists(b), false, false, Some(b)),
+        (Error::OutOfEntityIds, false, false, None),
+        (
+            Error::ComponentValidationFailed("Health", "negative".to_string()),
+            false,
+            false,
+            None,
+        ),
+        (Error::DuplicateComponentType("Health"), false, false, None),
+        (Error::AllocationFailed("Health"), false, false, None),
+    ];
+
+    for (error, is_invalid_entity, is_missing_component, entity_id) in cases {
+        assert_eq!(is_invalid_entity, error.is_invalid_entity(), "{:?}", error);
+        assert_eq!(
+            is_missing_component,
+            error.is_missing_component(),
+            "{:?}",
+            error
+        );
+        assert_eq!(entity_id, error.entity_id(), "{:?}", error);
+    }
+}
+
+#[test]
+fn sync_world_disjoint_components_across_threads() {
+    let mut world = SyncWorld::new();
+    world.register::<HealthComponent>();
+    world.register::<PositionComponent>();
+
+    let entities: Vec<EntityId> = (0..4).map(|_| world.create_entity()).collect();
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut healths = world.write_component::<HealthComponent>().unwrap();
+            for &entity_id in &entities {
+                healths.insert(entity_id, HealthComponent(100)).unwrap();
+            }
+        });
+        scope.spawn(|| {
+            let mut positions = world.write_component::<PositionComponent>().unwrap();
+            for &entity_id in &entities {
+                positions
+                    .insert(entity_id, PositionComponent([1, 1, 1]))
+                    .unwrap();
+            }
+        });
+    });
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let healths = world.read_component::<HealthComponent>().unwrap();
+            assert_eq!(
+                4,
+                healths.iter().filter(|(_, health)| health.0 == 100).count()
+            );
+        });
+        scope.spawn(|| {
+            let mut positions = world.write_component::<PositionComponent>().unwrap();
+            for (_, position) in positions.iter_mut() {
+                position.0[0] += 1;
+            }
+        });
+    });
+
+    let positions = world.read_component::<PositionComponent>().unwrap();
+    assert_eq!([2, 1, 1], positions.get(entities[0]).unwrap().0);
+    drop(positions);
+
+    world.despawn_entity(entities[0]).unwrap();
+    assert!(!world.is_entity_valid(entities[0]));
+    assert!(world.read_component::<PlayerTag>().is_err());
+}
+
+#[test]
+fn get_many_mut_two_ball_collision() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+    world.add_entity_component(a, HealthComponent(10)).unwrap();
+    world.add_entity_component(b, HealthComponent(20)).unwrap();
+
+    let [health_a, health_b] = world.get_many_mut::<HealthComponent, 2>([a, b]).unwrap();
+    std::mem::swap(&mut health_a.0, &mut health_b.0);
+    health_a.0 += 1;
+
+    assert_eq!(
+        21,
+        world.get_entity_component::<HealthComponent>(a).unwrap().0
+    );
+    assert_eq!(
+        10,
+        world.get_entity_component::<HealthComponent>(b).unwrap().0
+    );
+
+    assert_eq!(
+        Err(Error::DuplicateEntityId(a)),
+        world.get_many_mut::<HealthComponent, 2>([a, a]).map(|_| ())
+    );
+    assert_eq!(
+        Err(Error::InvalidEntityComponent(
+            std::any::type_name::<HealthComponent>(),
+            c
+        )),
+        world.get_many_mut::<HealthComponent, 2>([a, c]).map(|_| ())
+    );
+}
+
+#[test]
+fn create_entity_with_explicit_id() {
+    let mut world = World::new();
+
+    let first = world.create_entity();
+    let reserved = EntityId::from_raw(10);
+    world.create_entity_with_id(reserved).unwrap();
+    assert!(world.is_entity_valid(reserved));
+
+    assert_eq!(
+        Err(Error::EntityAlreadyExists(reserved)),
+        world.create_entity_with_id(reserved)
+    );
+    assert_eq!(
+        Err(Error::EntityAlreadyExists(first)),
+        world.create_entity_with_id(first)
+    );
+
+    let next = world.create_entity();
+    assert!(next > reserved);
+
+    // Reserving below the counter doesn't move it back.
+    world.create_entity_with_id(EntityId::from_raw(5)).unwrap();
+    assert!(world.create_entity() > next);
+}
+
+#[test]
+fn create_entity_with_id_rejects_live_index() {
+    for mut world in [World::new(), World::with_dense_capacity(4)] {
+        world.enable_bitset::<HealthComponent>();
+        world.enable_bitset::<PositionComponent>();
+        let live = world.create_entity();
+        world
+            .add_entity_component(live, HealthComponent(1))
+            .unwrap();
+        world
+            .add_entity_component(live, PositionComponent([1; 3]))
+            .unwrap();
+
+        // Dense storages and bitsets have one slot per index, the two would share it.
+        let same_index = EntityId::from_raw_parts(live.index(), live.generation() + 1);
+        assert_eq!(
+            Err(Error::EntityAlreadyExists(live)),
+            world.create_entity_with_id(same_index)
+        );
+        assert!(!world.is_entity_valid(same_index));
+        assert_eq!(
+            1,
+            world.query2::<HealthComponent, PositionComponent>().count()
+        );
+        world.check_consistency().unwrap();
+
+        // Once it's despawned the index is free to take under any generation.
+        world.despawn_entity(live).unwrap();
+        world.create_entity_with_id(same_index).unwrap();
+        assert_ne!(same_index, world.create_entity());
+    }
+}
+
+#[test]
+fn explicit_ids_skip_their_free_index() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.despawn_entity(a).unwrap();
+    world.despawn_entity(b).unwrap();
+
+    // Both indices are free, the explicit id takes the one that would be recycled first.
+    let picked = EntityId::from_raw_parts(b.index(), 7);
+    world.create_entity_with_id(picked).unwrap();
+    let recycled = world.create_entity();
+    assert_eq!(a.index(), recycled.index());
+    assert_eq!(2, world.create_entity().index());
+    world.check_consistency().unwrap();
+
+    world.despawn_entity(picked).unwrap();
+    assert_eq!(b.index(), world.create_entity().index());
+    assert_eq!(3, world.create_entity().index());
+    world.check_consistency().unwrap();
+}
+
+#[test]
+#[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+fn bench_create_entities_with_allocator() {
+    struct Counter(usize);
+
+    impl IdAllocator for Counter {
+        fn next(&mut self) -> EntityId {
+            self.0 += 1;
+            EntityId::from_raw(self.0)
+        }
+    }
+
+    let start = std::time::Instant::now();
+    let mut world = World::with_allocator(Counter(0));
+    for _ in 0..20_000 {
+        world.create_entity();
+    }
+    println!("20k entities from an allocator: {:?}", start.elapsed());
+    assert_eq!(20_000, world.len());
+}
+
+#[test]
+fn registered_components_lists_storages() {
+    let mut world = World::new();
+    assert_eq!(0, world.registered_components().count());
+
+    let player_id = world.create_entity();
+    world.add_entity_component(player_id, PlayerTag).unwrap();
+    world.ensure_component_registered::<HealthComponent>();
+
+    let registered: HashSet<ComponentId> = world.registered_components().collect();
+    assert_eq!(
+        HashSet::from([
+            ComponentId::of::<PlayerTag>(),
+            ComponentId::of::<HealthComponent>()
+        ]),
+        registered
+    );
+}
+
+#[test]
+fn component_name_is_recorded_on_registration() {
+    let mut world = World::new();
+    assert_eq!(
+        None,
+        world.component_name(ComponentId::of::<HealthComponent>())
+    );
+
+    world.ensure_component_registered::<HealthComponent>();
+    assert_eq!(
+        Some(std::any::type_name::<HealthComponent>()),
+        world.component_name(ComponentId::of::<HealthComponent>())
+    );
+}
+
+#[test]
+fn debug_entity_lists_components() {
+    let mut world = World::new();
+    world.register_debuggable::<VelocityComponent>();
+
+    let player_id = world.create_entity();
+    world
+        .add_entity_component(player_id, HealthComponent(100))
+        .unwrap();
+    world.add_entity_component(player_id, PlayerTag).unwrap();
+    world
+        .add_entity_component(player_id, VelocityComponent([1, 2, 3]))
+        .unwrap();
+
+    let other_id = world.create_entity();
+    world.add_entity_component(other_id, PlayerTag).unwrap();
+
+    assert_eq!(
+        format!(
+            "Entity {}: HealthComponent, PlayerTag, VelocityComponent([1, 2, 3])",
+            player_id
+        ),
+        world.debug_entity(player_id)
+    );
+    assert_eq!(
+        format!("Entity {}: PlayerTag", other_id),
+        world.debug_entity(other_id)
+    );
+}
+
+#[test]
+fn stable_removal_keeps_order() {
+    let mut world = World::new();
+
+    let entities: Vec<EntityId> = (0..5).map(|_| world.create_entity()).collect();
+    for (i, &entity_id) in entities.iter().enumerate() {
+        world
+            .add_entity_component(entity_id, HealthComponent(i as i32))
+            .unwrap();
+    }
+
+    assert_eq!(
+        1,
+        world
+            .remove_entity_component_stable::<HealthComponent>(entities[1])
+            .unwrap()
+            .0
+    );
+    assert!(
+        world
+            .remove_entity_component_stable::<HealthComponent>(entities[1])
+            .is_err()
+    );
+
+    assert_eq!(
+        vec![entities[0], entities[2], entities[3], entities[4]],
+        world.entities_with::<HealthComponent>()
+    );
+    for i in [0, 2, 3, 4] {
+        assert_eq!(
+            i as i32,
+            world
+                .get_entity_component::<HealthComponent>(entities[i])
+                .unwrap()
+                .0
+        );
+    }
+
+    world.compact::<HealthComponent>();
+    assert_eq!(
+        3,
+        world
+            .get_entity_component::<HealthComponent>(entities[3])
+            .unwrap()
+            .0
+    );
+}
+
+#[test]
+fn stable_removal_keeps_slot_indices() {
+    let mut world = World::new();
+
+    let entities: Vec<EntityId> = (0..5).map(|_| world.create_entity()).collect();
+    for (i, &entity_id) in entities.iter().enumerate() {
+        world
+            .add_entity_component(entity_id, HealthComponent(i as i32))
+            .unwrap();
+    }
+    let slot_of = |world: &World, entity_id| {
+        world
+            .get_component_storage::<HealthComponent>()
+            .unwrap()
+            .entity_component_map
+            .get(&entity_id)
+            .copied()
+    };
+
+    world
+        .remove_entity_component_stable::<HealthComponent>(entities[1])
+        .unwrap();
+    // Once tombstoned, plain removals don't move the last component either.
+    world
+        .remove_entity_component::<HealthComponent>(entities[2])
+        .unwrap();
+    for i in [0, 3, 4] {
+        assert_eq!(Some(i), slot_of(&world, entities[i]));
+    }
+    assert!(world.component_slice::<HealthComponent>().is_none());
+    assert_eq!(3, world.component_count::<HealthComponent>());
+    assert_eq!(3, world.iter_component::<HealthComponent>().count());
+
+    let new_entity = world.create_entity();
+    world
+        .add_entity_component(new_entity, HealthComponent(5))
+        .unwrap();
+    assert_eq!(Some(5), slot_of(&world, new_entity));
+    assert!(world.check_consistency().is_ok());
+
+    world.compact::<HealthComponent>();
+    assert_eq!(
+        vec![entities[0], entities[3], entities[4], new_entity],
+        world
+            .component_slice::<HealthComponent>()
+            .unwrap()
+            .iter()
+            .map(|(entity_id, _)| *entity_id)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(Some(1), slot_of(&world, entities[3]));
+    assert!(world.check_consistency().is_ok());
+}
+
+#[test]
+fn zero_sized_tags_are_stored_as_a_bitset() {
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..1000).map(|_| world.create_entity()).collect();
+    for &entity_id in &ids {
+        world.add_entity_component(entity_id, PlayerTag).unwrap();
+    }
+
+    // A bit and a generation per entity index, not an id and a map entry per tag.
+    let memory = world.memory_report()[&ComponentId::of::<PlayerTag>()];
+    assert!(
+        memory < ids.len() * std::mem::size_of::<EntityId>() / 2,
+        "{} bytes",
+        memory
+    );
+
+    let (a, b, c) = (ids[0], ids[1], ids[2]);
+    assert!(world.add_entity_component(b, PlayerTag).is_err());
+    world.remove_entity_component::<PlayerTag>(a).unwrap();
+    assert!(world.get_entity_component::<PlayerTag>(a).is_err());
+    assert!(world.get_entity_component::<PlayerTag>(b).is_ok());
+    // Index order, a removal doesn't move the last tag forward.
+    assert_eq!(&[b, c], &world.entities_with::<PlayerTag>()[..2]);
+    assert_eq!(999, world.component_count::<PlayerTag>());
+
+    world.add_entity_component(c, HealthComponent(5)).unwrap();
+    let tagged_health: Vec<EntityId> = world
+        .query2_mut::<PlayerTag, HealthComponent>()
+        .map(|(entity_id, _, _)| entity_id)
+        .collect();
+    assert_eq!(vec![c], tagged_health);
+    assert!(world.get_many_mut::<PlayerTag, 2>([b, c]).is_ok());
+    world.swap_components::<PlayerTag>(b, c).unwrap();
+
+    let mut peers_of_b = 0;
+    world.for_each_with_peers::<PlayerTag>(|entity_id, _, peers| {
+        if entity_id == b {
+            peers_of_b = peers.iter().count();
+            assert!(peers.get(c).is_some() && peers.get(b).is_none());
+        }
+    });
+    assert_eq!(998, peers_of_b);
+
+    let id_map = world.compact_ids();
+    world.check_consistency().unwrap();
+    assert!(world.has_component::<PlayerTag>(id_map[&c]));
+    assert_eq!(999, world.drain_components::<PlayerTag>().count());
+    assert_eq!(0, world.component_count::<PlayerTag>());
+}
+
+#[test]
+fn zero_sized_tags_are_dropped_once() {
+    use std::cell::Cell;
+
+    thread_local! {
+        static DROPPED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[derive(Clone)]
+    struct CountedTag;
+
+    impl Drop for CountedTag {
+        fn drop(&mut self) {
+            DROPPED.with(|dropped| dropped.set(dropped.get() + 1));
+        }
+    }
+
+    let dropped = || DROPPED.with(Cell::get);
+    {
+        let mut world = World::new();
+        world.register_clonable::<CountedTag>();
+        let ids: Vec<EntityId> = (0..4).map(|_| world.create_entity()).collect();
+        for &entity_id in &ids {
+            world.add_entity_component(entity_id, CountedTag).unwrap();
+        }
+
+        drop(world.remove_entity_component::<CountedTag>(ids[0]).unwrap());
+        assert_eq!(1, dropped());
+        world.despawn_entity(ids[1]).unwrap();
+        assert_eq!(2, dropped());
+
+        drop(world.deep_clone());
+        assert_eq!(4, dropped());
+    }
+    assert_eq!(6, dropped());
+}
+
+#[test]
+fn iter_entities_sorted_is_ascending() {
+    let mut world = World::new();
+
+    let entities: Vec<EntityId> = (0..10).map(|_| world.create_entity()).collect();
+    world.despawn_entity(entities[3]).unwrap();
+
+    assert_eq!(9, world.iter_entities().count());
+
+    let sorted: Vec<EntityId> = world.iter_entities_sorted().collect();
+    assert!(sorted.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(!sorted.contains(&entities[3]));
+    assert_eq!(9, sorted.len());
+}
+
+#[test]
+fn try_create_entity_reports_exhaustion() {
+    let mut world = World::new();
+
+    world
+        .create_entity_with_id(EntityId::from_raw(usize::MAX - 2))
+        .unwrap();
+
+    assert_eq!(
+        Ok(EntityId::from_raw(usize::MAX - 1)),
+        world.try_create_entity()
+    );
+    assert_eq!(Err(Error::OutOfEntityIds), world.try_create_entity());
+    assert_eq!(Err(Error::OutOfEntityIds), world.try_create_entity());
+    assert_eq!(2, world.len());
+}
+
+#[test]
+fn clear_component_leaves_others() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+    world.add_entity_component(a, PlayerTag).unwrap();
+
+    world.clear_component::<HealthComponent>();
+
+    assert!(world.entities_with::<HealthComponent>().is_empty());
+    assert!(matches!(
+        world.get_entity_component::<HealthComponent>(a),
+        Err(Error::InvalidEntityComponent(_, _))
+    ));
+    assert_eq!(vec![a], world.entities_with::<PlayerTag>());
+
+    // Still usable afterwards.
+    world.add_entity_component(b, HealthComponent(3)).unwrap();
+    assert_eq!(
+        3,
+        world.get_entity_component::<HealthComponent>(b).unwrap().0
+    );
+
+    // Clearing something never registered is fine.
+    world.clear_component::<PositionComponent>();
+}
+
+#[test]
+fn hooks_count_add_and_remove() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut world = World::new();
+
+    let added = Rc::new(RefCell::new(Vec::new()));
+    let removed = Rc::new(RefCell::new(Vec::new()));
+    {
+        let added = added.clone();
+        world.on_add::<HealthComponent>(move |entity_id| added.borrow_mut().push(entity_id));
+        let removed = removed.clone();
+        world.on_remove::<HealthComponent>(move |entity_id| removed.borrow_mut().push(entity_id));
+    }
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+    world.add_entity_component(b, PlayerTag).unwrap();
+    // Failed adds don't count.
+    assert!(world.add_entity_component(a, HealthComponent(1)).is_err());
+
+    world.remove_entity_component::<HealthComponent>(a).unwrap();
+    world.despawn_entity(b).unwrap();
+
+    assert_eq!(vec![a, b], *added.borrow());
+    assert_eq!(vec![a, b], *removed.borrow());
+}
+
+#[test]
+fn hierarchy_removals_run_hooks() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut world = World::new();
+
+    let parents_removed = Rc::new(RefCell::new(Vec::new()));
+    let children_removed = Rc::new(RefCell::new(Vec::new()));
+    {
+        let parents_removed = parents_removed.clone();
+        world.on_remove::<Parent>(move |entity_id| parents_removed.borrow_mut().push(entity_id));
+        let children_removed = children_removed.clone();
+        world.on_remove::<Children>(move |entity_id| children_removed.borrow_mut().push(entity_id));
+    }
+
+    let root = world.create_entity();
+    let kept = world.create_entity();
+    let dropped = world.create_entity();
+    let grandchild = world.create_entity();
+    world.set_parent(kept, root).unwrap();
+    world.set_parent(dropped, root).unwrap();
+    world.set_parent(grandchild, kept).unwrap();
+
+    world.remove_parent(dropped).unwrap();
+    assert_eq!(vec![dropped], *parents_removed.borrow());
+    assert!(children_removed.borrow().is_empty());
+
+    // The root loses its last child, then the despawned entity its parent and children.
+    world.despawn_entity(kept).unwrap();
+    assert_eq!(vec![dropped, kept, grandchild], *parents_removed.borrow());
+    assert_eq!(vec![root, kept], *children_removed.borrow());
+}
+
+#[test]
+fn replace_entity_component_returns_old() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+
+    let old = world
+        .replace_entity_component(a, HealthComponent(10))
+        .unwrap();
+    assert_eq!(1, old.0);
+    assert_eq!(
+        10,
+        world.get_entity_component::<HealthComponent>(a).unwrap().0
+    );
+    // Nothing moved around.
+    assert_eq!(vec![a, b], world.entities_with::<HealthComponent>());
+
+    assert!(matches!(
+        world.replace_entity_component(a, PlayerTag),
+        Err(Error::InvalidWorldComponent(_))
+    ));
+    world.add_entity_component(b, PlayerTag).unwrap();
+    assert!(matches!(
+        world.replace_entity_component(a, PlayerTag),
+        Err(Error::InvalidEntityComponent(_, _))
+    ));
+
+    world
+        .add_entity_component(a, Name("old".to_string()))
+        .unwrap();
+    world
+        .replace_entity_component(a, Name("new".to_string()))
+        .unwrap();
+    assert_eq!(None, world.find_by_name("old"));
+    assert_eq!(Some(a), world.find_by_name("new"));
+}
+
+#[test]
+fn map_component_increments_health() {
+    let mut world = World::new();
+
+    let player_id = world.create_entity();
+    world
+        .add_entity_component(player_id, HealthComponent(10))
+        .unwrap();
+
+    world
+        .map_component::<HealthComponent, _>(player_id, |health| health.0 += 5)
+        .unwrap();
+    assert_eq!(
+        15,
+        world
+            .get_entity_component::<HealthComponent>(player_id)
+            .unwrap()
+            .0
+    );
+
+    let mut ran = false;
+    assert!(
+        world
+            .map_component::<PositionComponent, _>(player_id, |_| ran = true)
+            .is_err()
+    );
+    assert!(!ran);
+}
+
+#[test]
+fn query2_opt_with_and_without_optional() {
+    let mut world = World::new();
+
+    let frozen = world.create_entity();
+    let moving = world.create_entity();
+    let static_one = world.create_entity();
+
+    for entity_id in [frozen, moving, static_one] {
+        world
+            .add_entity_component(entity_id, PositionComponent([0, 0, 0]))
+            .unwrap();
+    }
+    world
+        .add_entity_component(frozen, VelocityComponent([1, 0, 0]))
+        .unwrap();
+    world
+        .add_entity_component(moving, VelocityComponent([0, 1, 0]))
+        .unwrap();
+    world.add_entity_component(frozen, PlayerTag).unwrap();
+
+    let mut results: Vec<(EntityId, [i32; 3], bool)> = world
+        .query2_opt::<PositionComponent, VelocityComponent, PlayerTag>()
+        .map(|(entity_id, _, velocity, tag)| (entity_id, velocity.0, tag.is_some()))
+        .collect();
+    results.sort();
+
+    assert_eq!(
+        vec![(frozen, [1, 0, 0], true), (moving, [0, 1, 0], false)],
+        results
+    );
+
+    assert_eq!(
+        2,
+        world
+            .query2::<VelocityComponent, PositionComponent>()
+            .count()
+    );
+    assert_eq!(
+        0,
+        world.query2::<PositionComponent, HealthComponent>().count()
+    );
+}
+
+#[test]
+fn bitset_join_matches_probed_join() {
+    let mut world = World::new();
+
+    let entities: Vec<EntityId> = (0..200).map(|_| world.create_entity()).collect();
+    // Storage order is the reverse of index order, which a bitset join yields in.
+    for (i, &entity_id) in entities.iter().enumerate().rev() {
+        if i % 3 == 0 {
+            world
+                .add_entity_component(entity_id, HealthComponent(i as i32))
+                .unwrap();
+        }
+        if i % 5 == 0 {
+            world
+                .add_entity_component(entity_id, PositionComponent([i as i32; 3]))
+                .unwrap();
+        }
+    }
+
+    let mut probed: Vec<EntityId> = world
+        .query2::<HealthComponent, PositionComponent>()
+        .map(|(entity_id, _, _)| entity_id)
+        .collect();
+    probed.sort();
+
+    world.enable_bitset::<HealthComponent>();
+    world.enable_bitset::<PositionComponent>();
+    world
+        .remove_entity_component::<HealthComponent>(entities[15])
+        .unwrap();
+    world
+        .remove_entity_component_stable::<PositionComponent>(entities[30])
+        .unwrap();
+    probed.retain(|&entity_id| entity_id != entities[15] && entity_id != entities[30]);
+
+    let joined: Vec<(EntityId, i32, i32)> = world
+        .query2::<HealthComponent, PositionComponent>()
+        .map(|(entity_id, health, position)| (entity_id, health.0, position.0[0]))
+        .collect();
+
+    assert_eq!(
+        probed,
+        joined
+            .iter()
+            .map(|(entity_id, _, _)| *entity_id)
+            .collect::<Vec<_>>()
+    );
+    assert!(
+        joined
+            .iter()
+            .all(|(_, health, position)| health == position)
+    );
+}
+
+#[test]
+#[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+fn bench_bitset_join_with_sparse_overlap() {
+    use std::time::{Duration, Instant};
+
+    fn time_joins(world: &World) -> (usize, Duration) {
+        let start = Instant::now();
+        let mut joined = 0;
+        for _ in 0..100 {
+            for row in world.query2::<HealthComponent, PositionComponent>() {
+                std::hint::black_box(row);
+                joined += 1;
+            }
+        }
+        (joined, start.elapsed())
+    }
+
+    // Two big storages that only share one entity in a thousand.
+    let mut world = World::new();
+    for i in 0..100_000 {
+        let entity_id = world.create_entity();
+        if i < 50_000 || i % 1000 == 0 {
+            world
+                .add_entity_component(entity_id, HealthComponent(i))
+                .unwrap();
+        }
+        if i >= 50_000 || i % 1000 == 0 {
+            world
+                .add_entity_component(entity_id, PositionComponent([i; 3]))
+                .unwrap();
+        }
+    }
+
+    let (probed_rows, probed) = time_joins(&world);
+    world.enable_bitset::<HealthComponent>();
+    world.enable_bitset::<PositionComponent>();
+    let (bitset_rows, bitset) = time_joins(&world);
+
+    println!(
+        "sparse overlap join: probed {:?}, bitsets {:?}",
+        probed, bitset
+    );
+    assert_eq!(100 * 100, probed_rows);
+    assert_eq!(probed_rows, bitset_rows);
+    assert!(bitset < probed);
+}
+
+#[test]
+fn memory_report_grows_with_components() {
+    let mut world = World::new();
+    world.ensure_component_registered::<PositionComponent>();
+
+    let position_id = ComponentId::of::<PositionComponent>();
+    let empty = world.memory_report()[&position_id];
+
+    for _ in 0..1000 {
+        let entity_id = world.create_entity();
+        world
+            .add_entity_component(entity_id, PositionComponent([0, 0, 0]))
+            .unwrap();
+    }
+
+    let report = world.memory_report();
+    assert_eq!(1, report.len());
+    assert!(
+        report[&position_id] >= empty + 1000 * std::mem::size_of::<(EntityId, PositionComponent)>()
+    );
+}
+
+#[test]
+fn iter_components_sorted_by_field() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+    world
+        .add_entity_component(a, PositionComponent([0, 0, 5]))
+        .unwrap();
+    world
+        .add_entity_component(b, PositionComponent([0, 0, -1]))
+        .unwrap();
+    world
+        .add_entity_component(c, PositionComponent([0, 0, 3]))
+        .unwrap();
+
+    let depths: Vec<(EntityId, i32)> = world
+        .iter_components_sorted_by::<PositionComponent, _, _>(|position| position.0[2])
+        .into_iter()
+        .map(|(entity_id, position)| (entity_id, position.0[2]))
+        .collect();
+    assert_eq!(vec![(b, -1), (c, 3), (a, 5)], depths);
+
+    assert!(
+        world
+            .iter_components_sorted_by::<HealthComponent, _, _>(|health| health.0)
+            .is_empty()
+    );
+}
+
+#[test]
+fn check_consistency_catches_desync() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+    world.add_entity_component(a, PlayerTag).unwrap();
+    world.remove_entity_component::<HealthComponent>(a).unwrap();
+
+    assert_eq!(Ok(()), world.check_consistency());
+    world.debug_assert_consistent();
+
+    // Point `b` at a slot that isn't its own.
+    world.add_entity_component(a, HealthComponent(3)).unwrap();
+    let component_storage = world
+        .get_component_storage_mut::<HealthComponent>()
+        .unwrap();
+    component_storage.entity_component_map.insert(b, 1);
+    assert!(world.check_consistency().is_err());
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic]
+fn debug_assert_consistent_panics_on_desync() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world
+        .get_component_storage_mut::<HealthComponent>()
+        .unwrap()
+        .components
+        .clear();
+
+    world.debug_assert_consistent();
+}
+
+#[test]
+fn failed_add_does_not_mutate() {
+    let mut world = World::new();
+
+    let mut other_world = World::new();
+    let foreign_id = other_world.create_entity();
+    other_world.create_entity();
+    assert!(world.add_entity_component(foreign_id, PlayerTag).is_err());
+    assert_eq!(
+        None,
+        world.component_name(ComponentId::of::<PlayerTag>()),
+        "An invalid entity shouldn't register the component."
+    );
+
+    let player_id = world.create_entity();
+    world
+        .add_entity_component(player_id, HealthComponent(1))
+        .unwrap();
+
+    assert!(
+        world
+            .add_entity_component(player_id, HealthComponent(2))
+            .is_err()
+    );
+
+    let component_storage = world.get_component_storage::<HealthComponent>().unwrap();
+    assert_eq!(1, component_storage.components.len());
+    assert_eq!(1, component_storage.entity_component_map.len());
+    assert_eq!(
+        1,
+        world
+            .get_entity_component::<HealthComponent>(player_id)
+            .unwrap()
+            .0
+    );
+    assert_eq!(Ok(()), world.check_consistency());
+}
+
+#[test]
+fn drain_components_empties_storage() {
+    let mut world = World::new();
+
+    let entities: Vec<EntityId> = (0..3).map(|_| world.create_entity()).collect();
+    for (i, &entity_id) in entities.iter().enumerate() {
+        world
+            .add_entity_component(entity_id, HealthComponent(i as i32))
+            .unwrap();
+    }
+
+    let drained: Vec<(EntityId, i32)> = world
+        .drain_components::<HealthComponent>()
+        .map(|(entity_id, health)| (entity_id, health.0))
+        .collect();
+    assert_eq!(
+        vec![(entities[0], 0), (entities[1], 1), (entities[2], 2)],
+        drained
+    );
+    assert!(world.entities_with::<HealthComponent>().is_empty());
+    assert!(
+        world
+            .get_entity_component::<HealthComponent>(entities[0])
+            .is_err()
+    );
+
+    // Dropping the iterator early still empties it.
+    for &entity_id in &entities {
+        world
+            .add_entity_component(entity_id, HealthComponent(0))
+            .unwrap();
+    }
+    assert!(world.drain_components::<HealthComponent>().next().is_some());
+    assert!(world.entities_with::<HealthComponent>().is_empty());
+    assert_eq!(Ok(()), world.check_consistency());
+
+    assert_eq!(0, world.drain_components::<PlayerTag>().count());
+}
+
+#[test]
+fn lenient_get_treats_unregistered_as_absent() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+
+    // Never registered.
+    assert!(matches!(
+        world.get_entity_component::<PositionComponent>(a),
+        Err(Error::InvalidWorldComponent(_))
+    ));
+    assert!(
+        world
+            .get_entity_component_lenient::<PositionComponent>(a)
+            .unwrap()
+            .is_none()
+    );
+
+    // Registered, but the entity lacks it.
+    assert!(matches!(
+        world.get_entity_component::<HealthComponent>(b),
+        Err(Error::InvalidEntityComponent(_, _))
+    ));
+    assert!(
+        world
+            .get_entity_component_lenient::<HealthComponent>(b)
+            .unwrap()
+            .is_none()
+    );
+
+    assert_eq!(
+        1,
+        world
+            .get_entity_component_lenient::<HealthComponent>(a)
+            .unwrap()
+            .unwrap()
+            .0
+    );
+
+    world.despawn_entity(b).unwrap();
+    assert!(
+        world
+            .get_entity_component_lenient::<HealthComponent>(b)
+            .is_err()
+    );
+}
+
+#[test]
+fn contains_component_type_after_insert() {
+    let mut world = World::new();
+    assert!(!world.contains_component_type::<HealthComponent>());
+
+    let player_id = world.create_entity();
+    world
+        .add_entity_component(player_id, HealthComponent(1))
+        .unwrap();
+    assert!(world.contains_component_type::<HealthComponent>());
+
+    // Stays registered after the last one is gone.
+    world
+        .remove_entity_component::<HealthComponent>(player_id)
+        .unwrap();
+    assert!(world.contains_component_type::<HealthComponent>());
+    assert!(!world.contains_component_type::<PlayerTag>());
+}
+
+#[test]
+fn add_component_to_ten_entities() {
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..10).map(|_| world.create_entity()).collect();
+
+    world
+        .add_component_to_many(&ids, VelocityComponent([1, 0, 0]))
+        .unwrap();
+    for &id in &ids {
+        assert_eq!(
+            &VelocityComponent([1, 0, 0]),
+            world.get_entity_component::<VelocityComponent>(id).unwrap()
+        );
+    }
+
+    // One of them already has it, so nobody gets it.
+    let extra = world.create_entity();
+    assert_eq!(
+        Err(Error::ComponentAlreadyAdded(
+            std::any::type_name::<VelocityComponent>(),
+            ids[3]
+        )),
+        world.add_component_to_many(&[extra, ids[3]], VelocityComponent([2, 0, 0]))
+    );
+    assert!(
+        world
+            .get_entity_component::<VelocityComponent>(extra)
+            .is_err()
+    );
+    assert_eq!(
+        Err(Error::DuplicateEntityId(extra)),
+        world.add_component_to_many(&[extra, extra], VelocityComponent([2, 0, 0]))
+    );
+}
+
+#[test]
+fn entity_id_raw_round_trip() {
+    let mut world = World::new();
+    world.create_entity();
+    let player_id = world.create_entity();
+
+    let raw = player_id.index();
+    assert_eq!(player_id, EntityId::from_raw(raw));
+    assert_eq!(raw.to_string(), player_id.to_string());
+    assert!(world.is_entity_valid(EntityId::from_raw(raw)));
+}
+
+#[test]
+fn count_query2_matches_expected() {
+    let mut world = World::new();
+    let mut expected = HashSet::new();
+    for i in 0..20 {
+        let entity_id = world.create_entity();
+        if i % 2 == 0 {
+            world
+                .add_entity_component(entity_id, HealthComponent(i))
+                .unwrap();
+        }
+        if i % 3 == 0 {
+            world
+                .add_entity_component(entity_id, PositionComponent([i, 0, 0]))
+                .unwrap();
+        }
+        if i % 6 == 0 {
+            expected.insert(entity_id);
+        }
+    }
+
+    assert_eq!(
+        expected.len(),
+        world.count_query2::<HealthComponent, PositionComponent>()
+    );
+    assert_eq!(
+        expected,
+        world
+            .query2::<HealthComponent, PositionComponent>()
+            .map(|(entity_id, _, _)| entity_id)
+            .collect()
+    );
+    assert_eq!(0, world.count_query2::<HealthComponent, PlayerTag>());
+}
+
+#[test]
+fn drain_changes_in_order() {
+    let mut world = World::new();
+    let player_id = world.create_entity();
+    let enemy_id = world.create_entity();
+
+    // Not tracked yet.
+    world
+        .add_entity_component(enemy_id, HealthComponent(5))
+        .unwrap();
+    world.track_changes::<HealthComponent>();
+
+    world
+        .add_entity_component(player_id, HealthComponent(10))
+        .unwrap();
+    world
+        .get_entity_component_mut::<HealthComponent>(player_id)
+        .unwrap()
+        .0 -= 1;
+    // Swap-removes so the enemy's slot moves, which isn't a change to its data.
+    world
+        .remove_entity_component::<HealthComponent>(enemy_id)
+        .unwrap();
+    world
+        .remove_entity_component::<HealthComponent>(player_id)
+        .unwrap();
+
+    assert_eq!(
+        vec![
+            (player_id, ChangeKind::Added),
+            (player_id, ChangeKind::Modified),
+            (enemy_id, ChangeKind::Removed),
+            (player_id, ChangeKind::Removed),
+        ],
+        world.drain_changes::<HealthComponent>()
+    );
+    assert!(world.drain_changes::<HealthComponent>().is_empty());
+    assert!(world.drain_changes::<PlayerTag>().is_empty());
+}
+
+#[test]
+fn world_views_query_concurrently() {
+    let mut world = World::new();
+    for i in 0..100 {
+        let entity_id = world.create_entity();
+        world
+            .add_entity_component(entity_id, HealthComponent(i))
+            .unwrap();
+        if i % 2 == 0 {
+            world
+                .add_entity_component(entity_id, PositionComponent([i, 0, 0]))
+                .unwrap();
+        }
+    }
+
+    let sums: Vec<i32> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let view = world.view();
+                scope.spawn(move || {
+                    view.query2::<HealthComponent, PositionComponent>()
+                        .map(|(_, health, _)| health.0)
+                        .sum()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let expected: i32 = (0..100).step_by(2).sum();
+    assert_eq!(vec![expected; 4], sums);
+}
+
+#[test]
+fn component_accessor_lookups() {
+    let mut world = World::new();
+    assert!(world.component_accessor::<HealthComponent>().is_none());
+
+    let ids: Vec<EntityId> = (0..10).map(|_| world.create_entity()).collect();
+    for (i, &entity_id) in ids.iter().enumerate().skip(1) {
+        world
+            .add_entity_component(entity_id, HealthComponent(i as i32))
+            .unwrap();
+    }
+
+    let health = world.component_accessor::<HealthComponent>().unwrap();
+    assert_eq!(9, health.len());
+    assert!(health.get(ids[0]).is_none());
+    for (i, &entity_id) in ids.iter().enumerate().skip(1) {
+        assert_eq!(i as i32, health.get(entity_id).unwrap().0);
+    }
+    assert_eq!(45, health.iter().map(|(_, health)| health.0).sum::<i32>());
+}
+
+#[test]
+fn with_capacity_behaves_like_new() {
+    let mut world = World::with_capacity(100, 4);
+    assert!(world.is_empty());
+    assert_eq!(0, world.registered_components().count());
+
+    let player_id = world.create_entity();
+    assert_eq!(EntityId::from_raw(0), player_id);
+    world
+        .add_entity_component(player_id, HealthComponent(3))
+        .unwrap();
+    assert_eq!(
+        3,
+        world
+            .get_entity_component::<HealthComponent>(player_id)
+            .unwrap()
+            .0
+    );
+    assert!(world.entity_validity_set.capacity() >= 100);
+    assert!(world.component_storage_vecs.capacity() >= 4);
+}
+
+#[test]
+fn validator_rejects_bad_components() {
+    let mut world = World::new();
+    world.register_validator::<HealthComponent>(|health| {
+        if health.0 < 0 {
+            Err(format!("health {} is negative", health.0))
+        } else {
+            Ok(())
+        }
+    });
+
+    let player_id = world.create_entity();
+    assert_eq!(
+        Err(Error::ComponentValidationFailed(
+            std::any::type_name::<HealthComponent>(),
+            "health -1 is negative".to_string()
+        )),
+        world.add_entity_component(player_id, HealthComponent(-1))
+    );
+    assert!(
+        world
+            .get_entity_component::<HealthComponent>(player_id)
+            .is_err()
+    );
+
+    world
+        .add_entity_component(player_id, HealthComponent(1))
+        .unwrap();
+    assert!(
+        world
+            .replace_entity_component(player_id, HealthComponent(-5))
+            .is_err()
+    );
+    assert_eq!(
+        1,
+        world
+            .get_entity_component::<HealthComponent>(player_id)
+            .unwrap()
+            .0
+    );
+}
+
+#[test]
+fn entities_without_component() {
+    let mut world = World::new();
+    let bare_id = world.create_entity();
+    let player_id = world.create_entity();
+    let enemy_id = world.create_entity();
+    world.add_entity_component(player_id, PlayerTag).unwrap();
+    world
+        .add_entity_component(enemy_id, HealthComponent(2))
+        .unwrap();
+
+    assert_eq!(
+        HashSet::from([bare_id, enemy_id]),
+        world.entities_without::<PlayerTag>().collect()
+    );
+    assert_eq!(3, world.entities_without::<PositionComponent>().count());
+}
+
+#[test]
+fn component_slice_matches_count() {
+    let mut world = World::new();
+    assert!(world.component_slice::<HealthComponent>().is_none());
+
+    for i in 0..5 {
+        let entity_id = world.create_entity();
+        world
+            .add_entity_component(entity_id, HealthComponent(i))
+            .unwrap();
+    }
+
+    for (_, health) in world.component_slice_mut::<HealthComponent>().unwrap() {
+        health.0 *= 2;
+    }
+
+    let slice = world.component_slice::<HealthComponent>().unwrap();
+    assert_eq!(world.component_count::<HealthComponent>(), slice.len());
+    assert_eq!(20, slice.iter().map(|(_, health)| health.0).sum::<i32>());
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn multiple_entities_not_mixed_soa() {
+    let mut world = World::new();
+    world.enable_soa::<PositionComponent>();
+    world.enable_soa::<HealthComponent>();
+    world.enable_soa::<PlayerTag>();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+
+    world
+        .add_entity_component(c, PositionComponent([2, 2, 2]))
+        .unwrap();
+    world
+        .add_entity_component(a, PositionComponent([0, 0, 0]))
+        .unwrap();
+    world
+        .add_entity_component(b, PositionComponent([1, 1, 1]))
+        .unwrap();
+
+    world.add_entity_component(b, PlayerTag).unwrap();
+    world.add_entity_component(a, PlayerTag).unwrap();
+
+    world.add_entity_component(b, HealthComponent(1)).unwrap();
+    world.add_entity_component(c, HealthComponent(2)).unwrap();
+    world.add_entity_component(a, HealthComponent(0)).unwrap();
+
+    assert_eq!(
+        0,
+        world
+            .remove_entity_component::<HealthComponent>(a)
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        1,
+        world
+            .remove_entity_component::<HealthComponent>(b)
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        2,
+        world
+            .remove_entity_component::<HealthComponent>(c)
+            .unwrap()
+            .0
+    );
+
+    assert_eq!(
+        [2, 2, 2],
+        world
+            .remove_entity_component::<PositionComponent>(c)
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        [0, 0, 0],
+        world
+            .remove_entity_component::<PositionComponent>(a)
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        [1, 1, 1],
+        world
+            .remove_entity_component::<PositionComponent>(b)
+            .unwrap()
+            .0
+    );
+
+    assert!(world.remove_entity_component::<PlayerTag>(a).is_ok());
+    assert!(world.remove_entity_component::<PlayerTag>(b).is_ok());
+    assert!(world.remove_entity_component::<PlayerTag>(c).is_err());
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn soa_data_slice_is_contiguous() {
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..4).map(|_| world.create_entity()).collect();
+    for (i, &entity_id) in ids.iter().enumerate() {
+        world
+            .add_entity_component(entity_id, HealthComponent(i as i32))
+            .unwrap();
+    }
+    assert!(world.component_data_slice::<HealthComponent>().is_none());
+
+    // Switching over keeps what's already stored.
+    world.enable_soa::<HealthComponent>();
+    assert_eq!(
+        Some(ComponentStorageType::SoA),
+        world.storage_type::<HealthComponent>()
+    );
+    assert!(world.component_slice::<HealthComponent>().is_none());
+
+    for health in world.component_data_slice_mut::<HealthComponent>().unwrap() {
+        health.0 *= 10;
+    }
+    world
+        .swap_components::<HealthComponent>(ids[0], ids[3])
+        .unwrap();
+    world
+        .remove_entity_component_stable::<HealthComponent>(ids[1])
+        .unwrap();
+    // The tombstone has to go before the data is contiguous again.
+    assert!(world.component_data_slice::<HealthComponent>().is_none());
+    world.compact::<HealthComponent>();
+
+    let healths: Vec<i32> = world
+        .component_data_slice::<HealthComponent>()
+        .unwrap()
+        .iter()
+        .map(|health| health.0)
+        .collect();
+    assert_eq!(vec![30, 20, 0], healths);
+    assert_eq!(
+        vec![ids[0], ids[2], ids[3]],
+        world.entities_with::<HealthComponent>()
+    );
+    assert_eq!(
+        0,
+        world
+            .get_entity_component::<HealthComponent>(ids[3])
+            .unwrap()
+            .0
+    );
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn mutable_queries_across_types() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+    world
+        .add_entity_component(b, PositionComponent([0, 0, 0]))
+        .unwrap();
+    world
+        .add_entity_component(b, VelocityComponent([1, 2, 3]))
+        .unwrap();
+
+    for (_, health, position) in world.query2_mut::<HealthComponent, PositionComponent>() {
+        health.0 += 10;
+        position.0[0] += 1;
+    }
+    for (_, position, velocity, health) in
+        world.query3_mut::<PositionComponent, VelocityComponent, HealthComponent>()
+    {
+        for axis in 0..3 {
+            position.0[axis] += velocity.0[axis];
+        }
+        health.0 *= 2;
+    }
+
+    let (health, position) = world
+        .get_two_mut::<HealthComponent, PositionComponent>(b)
+        .unwrap();
+    assert_eq!(24, health.0);
+    assert_eq!([2, 2, 3], position.0);
+    assert_eq!(
+        1,
+        world.get_entity_component::<HealthComponent>(a).unwrap().0
+    );
+    assert!(
+        world
+            .get_two_mut::<HealthComponent, PositionComponent>(a)
+            .is_err()
+    );
+}
+
+#[test]
+fn mutable_queries_find_peers_through_their_maps() {
+    let mut world = World::new();
+    let entities: Vec<EntityId> = (0..6).map(|_| world.create_entity()).collect();
+    for (i, &entity_id) in entities.iter().enumerate() {
+        world
+            .add_entity_component(entity_id, HealthComponent(i as i32))
+            .unwrap();
+    }
+    // The peers are stored in the reverse order, one of them in SoA with a tombstone.
+    world.enable_soa::<PositionComponent>();
+    for (i, &entity_id) in entities.iter().enumerate().rev() {
+        world
+            .add_entity_component(entity_id, PositionComponent([i as i32, 0, 0]))
+            .unwrap();
+        world
+            .add_entity_component(entity_id, VelocityComponent([0, i as i32, 0]))
+            .unwrap();
+    }
+    world
+        .remove_entity_component_stable::<PositionComponent>(entities[2])
+        .unwrap();
+    world.set_active(entities[4], false).unwrap();
+
+    // All yielded at once, so the references have to be to different components.
+    let mut rows: Vec<_> = world
+        .query3_mut::<HealthComponent, PositionComponent, VelocityComponent>()
+        .collect();
+    assert_eq!(4, rows.len());
+    for (entity_id, health, position, velocity) in &mut rows {
+        assert_eq!(health.0, position.0[0]);
+        assert_eq!(health.0, velocity.0[1]);
+        assert_ne!(entities[2], *entity_id);
+        health.0 += 10;
+        position.0[0] += 10;
+    }
+
+    for (_, health, position) in world.query2_mut::<HealthComponent, PositionComponent>() {
+        assert_eq!(health.0, position.0[0]);
+    }
+    assert_eq!(
+        4,
+        world
+            .get_entity_component::<HealthComponent>(entities[4])
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        2,
+        world
+            .get_entity_component::<HealthComponent>(entities[2])
+            .unwrap()
+            .0
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "requested mutably more than once")]
+fn query2_mut_same_type_panics_in_debug() {
+    let mut world = World::new();
+    let player_id = world.create_entity();
+    world
+        .add_entity_component(player_id, HealthComponent(1))
+        .unwrap();
+
+    let _ = world.query2_mut::<HealthComponent, HealthComponent>();
+}
+
+#[test]
+fn despawn_all_with_tag() {
+    let mut world = World::new();
+    let mut survivors = HashSet::new();
+    for i in 0..10 {
+        let entity_id = world.create_entity();
+        world
+            .add_entity_component(entity_id, HealthComponent(i))
+            .unwrap();
+        if i % 3 == 0 {
+            world.add_entity_component(entity_id, PlayerTag).unwrap();
+        } else {
+            survivors.insert(entity_id);
+        }
+    }
+
+    assert_eq!(4, world.despawn_all_with::<PlayerTag>());
+    assert_eq!(survivors, world.iter_entities().collect());
+    assert_eq!(6, world.component_count::<HealthComponent>());
+    assert_eq!(0, world.despawn_all_with::<PlayerTag>());
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn map_all_stops_on_break() {
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..5).map(|_| world.create_entity()).collect();
+    for &entity_id in &ids {
+        world
+            .add_entity_component(entity_id, HealthComponent(0))
+            .unwrap();
+    }
+
+    let mut budget = 2;
+    world.map_all::<HealthComponent, _>(|_, health| {
+        health.0 += 1;
+        budget -= 1;
+        if budget == 0 {
+            std::ops::ControlFlow::Break(())
+        } else {
+            std::ops::ControlFlow::Continue(())
+        }
+    });
+
+    let healths: Vec<i32> = ids
+        .iter()
+        .map(|&entity_id| {
+            world
+                .get_entity_component::<HealthComponent>(entity_id)
+                .unwrap()
+                .0
+        })
+        .collect();
+    assert_eq!(vec![1, 1, 0, 0, 0], healths);
+}
+
+#[test]
+fn entity_signature_lists_component_types() {
+    let mut world = World::new();
+    let player_id = world.create_entity();
+    let enemy_id = world.create_entity();
+    world.add_entity_component(player_id, PlayerTag).unwrap();
+    world
+        .add_entity_component(player_id, HealthComponent(3))
+        .unwrap();
+    world
+        .add_entity_component(enemy_id, HealthComponent(1))
+        .unwrap();
+
+    assert_eq!(
+        vec![ComponentId::of::<HealthComponent>()],
+        world.entity_signature(enemy_id).unwrap()
+    );
+    let player_signature: HashSet<ComponentId> = world
+        .entity_signature(player_id)
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(
+        HashSet::from([
+            ComponentId::of::<HealthComponent>(),
+            ComponentId::of::<PlayerTag>()
+        ]),
+        player_signature
+    );
+
+    world.add_entity_component(enemy_id, PlayerTag).unwrap();
+    assert_eq!(
+        world.entity_signature(player_id),
+        world.entity_signature(enemy_id)
+    );
+}
+
+#[test]
+fn custom_id_allocator() {
+    struct EvenIds(usize);
+
+    impl IdAllocator for EvenIds {
+        fn next(&mut self) -> EntityId {
+            self.0 += 2;
+            EntityId::from_raw(self.0)
+        }
+    }
+
+    let mut world = World::with_allocator(EvenIds(0));
+    let ids: Vec<EntityId> = (0..5).map(|_| world.create_entity()).collect();
+    assert!(ids.iter().all(|entity_id| entity_id.index() % 2 == 0));
+    assert_eq!(EntityId::from_raw(10), ids[4]);
+
+    world.add_entity_component(ids[0], PlayerTag).unwrap();
+    assert!(world.get_entity_component::<PlayerTag>(ids[0]).is_ok());
+
+    // Taken by hand, so the allocator's next pick collides.
+    world.create_entity_with_id(EntityId::from_raw(12)).unwrap();
+    assert_eq!(
+        Err(Error::EntityAlreadyExists(EntityId::from_raw(12))),
+        world.try_create_entity()
+    );
+}
+
+#[test]
+fn unchecked_access_matches_checked() {
+    let mut world = World::new();
+    let player_id = world.create_entity();
+    world
+        .add_entity_component(player_id, HealthComponent(7))
+        .unwrap();
+
+    // SAFETY: `player_id` was just created and is never despawned.
+    unsafe {
+        assert_eq!(
+            world
+                .get_entity_component::<HealthComponent>(player_id)
+                .unwrap()
+                .0,
+            world
+                .get_entity_component_unchecked::<HealthComponent>(player_id)
+                .unwrap()
+                .0
+        );
+        world
+            .get_entity_component_unchecked_mut::<HealthComponent>(player_id)
+            .unwrap()
+            .0 += 1;
+        assert!(
+            world
+                .get_entity_component_unchecked::<PlayerTag>(player_id)
+                .is_err()
+        );
+    }
+    assert_eq!(
+        8,
+        world
+            .get_entity_component::<HealthComponent>(player_id)
+            .unwrap()
+            .0
+    );
+}
+
+#[test]
+fn visitor_counts_component_instances() {
+    struct Counter {
+        total: usize,
+        health: usize,
+    }
+
+    impl ComponentVisitor for Counter {
+        fn visit(&mut self, _: EntityId, component_id: ComponentId) {
+            self.total += 1;
+            if component_id == ComponentId::of::<HealthComponent>() {
+                self.health += 1;
+            }
+        }
+    }
+
+    let mut world = World::new();
+    for i in 0..4 {
+        let entity_id = world.create_entity();
+        world
+            .add_entity_component(entity_id, HealthComponent(i))
+            .unwrap();
+        if i % 2 == 0 {
+            world.add_entity_component(entity_id, PlayerTag).unwrap();
+        }
+    }
+    world.create_entity();
+
+    let mut counter = Counter {
+        total: 0,
+        health: 0,
+    };
+    world.accept(&mut counter);
+    assert_eq!(6, counter.total);
+    assert_eq!(4, counter.health);
+}
+
+#[test]
+fn inactive_entities_are_hidden() {
+    let mut world = World::new();
+    let pooled_id = world.create_entity();
+    let other_id = world.create_entity();
+    for entity_id in [pooled_id, other_id] {
+        world
+            .add_entity_component(entity_id, HealthComponent(1))
+            .unwrap();
+        world
+            .add_entity_component(entity_id, PositionComponent([0, 0, 0]))
+            .unwrap();
+    }
+
+    world.set_active(pooled_id, false).unwrap();
+    assert!(!world.is_entity_active(pooled_id));
+    assert!(world.is_entity_valid(pooled_id));
+    assert_eq!(vec![other_id], world.iter_entities().collect::<Vec<_>>());
+    assert_eq!(2, world.iter_entities_including_inactive().count());
+    assert_eq!(vec![other_id], world.entities_with::<HealthComponent>());
+    assert_eq!(
+        1,
+        world.count_query2::<HealthComponent, PositionComponent>()
+    );
+    assert_eq!(
+        vec![other_id],
+        world
+            .query2_mut::<HealthComponent, PositionComponent>()
+            .map(|(entity_id, _, _)| entity_id)
+            .collect::<Vec<_>>()
+    );
+    // Still reachable by id, components and all.
+    assert_eq!(
+        1,
+        world
+            .get_entity_component::<HealthComponent>(pooled_id)
+            .unwrap()
+            .0
+    );
+    world.check_consistency().unwrap();
+
+    world.set_active(pooled_id, true).unwrap();
+    assert_eq!(
+        2,
+        world.query2::<HealthComponent, PositionComponent>().count()
+    );
+
+    world.set_active(pooled_id, false).unwrap();
+    world.despawn_entity(pooled_id).unwrap();
+    assert!(world.set_active(pooled_id, true).is_err());
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn take_present_and_absent_component() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+
+    assert_eq!(1, world.take_component::<HealthComponent>(a).unwrap().0);
+    assert!(world.take_component::<HealthComponent>(a).is_none());
+    assert!(world.take_component::<PlayerTag>(b).is_none());
+
+    // The swap-remove moved `b`, it must still be found.
+    assert_eq!(
+        2,
+        world.get_entity_component::<HealthComponent>(b).unwrap().0
+    );
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn entity_ids_order_by_allocation() {
+    let mut world = World::new();
+    let first = world.create_entity();
+    let second = world.create_entity();
+    world.despawn_entity(first).unwrap();
+    // `third` reuses `first`'s index, under a newer generation.
+    let third = world.create_entity();
+    assert_eq!(first.index(), third.index());
+
+    assert!(second > first);
+    assert!(second.allocated_after(first));
+    assert!(third.allocated_after(second));
+    assert!(!first.allocated_after(first));
+    assert_eq!(first, EntityId::from_raw(first.index()));
+
+    let mut ids = vec![third, first, second];
+    ids.sort();
+    assert_eq!(vec![first, second, third], ids);
+}
+
+#[test]
+fn recycled_ids_keep_allocation_order() {
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..4).map(|_| world.create_entity()).collect();
+    world.despawn_entity(ids[3]).unwrap();
+
+    let recycled = world.create_entity();
+    let fresh = world.create_entity();
+    world.despawn_entity(ids[1]).unwrap();
+    let recycled_again = world.create_entity();
+
+    assert_eq!(ids[3].index(), recycled.index());
+    assert_eq!(4, fresh.index());
+    assert_eq!(ids[1].index(), recycled_again.index());
+    assert!(recycled.allocated_after(ids[2]));
+    assert!(fresh.allocated_after(recycled));
+    assert!(recycled_again.allocated_after(fresh));
+
+    let mut sorted = vec![recycled_again, fresh, ids[0], recycled, ids[2]];
+    sorted.sort();
+    assert_eq!(
+        vec![ids[0], ids[2], recycled, fresh, recycled_again],
+        sorted
+    );
+}
+
+#[test]
+fn registered_but_empty_is_a_different_error() {
+    let mut world = World::new();
+    let player_id = world.create_entity();
+
+    assert_eq!(
+        Some(Error::InvalidWorldComponent(std::any::type_name::<
+            HealthComponent,
+        >())),
+        world
+            .get_entity_component::<HealthComponent>(player_id)
+            .err()
+    );
+
+    world.ensure_component_registered::<HealthComponent>();
+    assert_eq!(
+        Some(Error::InvalidEntityComponent(
+            std::any::type_name::<HealthComponent>(),
+            player_id
+        )),
+        world
+            .get_entity_component::<HealthComponent>(player_id)
+            .err()
+    );
+
+    // Emptying the storage keeps it registered.
+    world
+        .add_entity_component(player_id, HealthComponent(1))
+        .unwrap();
+    world.clear_component::<HealthComponent>();
+    assert!(matches!(
+        world.get_entity_component_mut::<HealthComponent>(player_id),
+        Err(Error::InvalidEntityComponent(_, _))
+    ));
+}
+
+#[test]
+fn pooled_components_reuse_allocations() {
+    #[derive(Default)]
+    struct RenderData(Vec<u8>);
+
+    let mut world = World::new();
+    world.register_pooled::<RenderData>();
+
+    let first_id = world.create_entity();
+    world
+        .add_pooled_component::<RenderData>(first_id, |data| data.0.extend([1; 64]))
+        .unwrap();
+    let buffer = world
+        .get_entity_component::<RenderData>(first_id)
+        .unwrap()
+        .0
+        .as_ptr();
+    world.despawn_entity(first_id).unwrap();
+
+    let second_id = world.create_entity();
+    world
+        .add_pooled_component::<RenderData>(second_id, |data| data.0.clear())
+        .unwrap();
+    let data = world.get_entity_component::<RenderData>(second_id).unwrap();
+    assert!(data.0.is_empty());
+    assert!(data.0.capacity() >= 64);
+    assert_eq!(buffer, data.0.as_ptr());
+
+    // The pool is empty again, so this one starts from scratch.
+    let third_id = world.create_entity();
+    world
+        .add_pooled_component::<RenderData>(third_id, |_| {})
+        .unwrap();
+    assert_eq!(
+        0,
+        world
+            .get_entity_component::<RenderData>(third_id)
+            .unwrap()
+            .0
+            .capacity()
+    );
+}
+
+#[test]
+fn query_with_parent_two_levels() {
+    let mut world = World::new();
+    let root = world.create_entity();
+    let child = world.create_entity();
+    let grandchild = world.create_entity();
+    let orphan_parent = world.create_entity();
+    let orphan = world.create_entity();
+    world.set_parent(child, root).unwrap();
+    world.set_parent(grandchild, child).unwrap();
+    world.set_parent(orphan, orphan_parent).unwrap();
+
+    for (entity_id, x) in [(root, 1), (child, 2), (grandchild, 3), (orphan, 4)] {
+        world
+            .add_entity_component(entity_id, PositionComponent([x, 0, 0]))
+            .unwrap();
+    }
+
+    let mut rows: Vec<(EntityId, i32, Option<i32>)> = world
+        .query_with_parent::<PositionComponent>()
+        .map(|(entity_id, position, parent_position)| {
+            (
+                entity_id,
+                position.0[0],
+                parent_position.map(|position| position.0[0]),
+            )
+        })
+        .collect();
+    rows.sort();
+
+    assert_eq!(
+        vec![
+            (root, 1, None),
+            (child, 2, Some(1)),
+            (grandchild, 3, Some(2)),
+            // The parent exists but has no position.
+            (orphan, 4, None),
+        ],
+        rows
+    );
+}
+
+#[cfg(feature = "deterministic")]
+#[test]
+fn identical_worlds_iterate_identically() {
+    fn build() -> World {
+        let mut world = World::new();
+        for i in 0..50 {
+            let entity_id = world.create_entity();
+            if i % 3 != 0 {
+                world
+                    .add_entity_component(entity_id, HealthComponent(i))
+                    .unwrap();
+            }
+            world
+                .add_entity_component(entity_id, PositionComponent([i, 0, 0]))
+                .unwrap();
+            if i % 5 == 0 {
+                world.add_entity_component(entity_id, PlayerTag).unwrap();
+            }
+            if i % 7 == 0 {
+                world.despawn_entity(entity_id).unwrap();
+            }
+        }
+        world
+    }
+
+    // Both come straight out of hashed collections, without a fixed seed they'd differ.
+    let (a, b) = (build(), build());
+    assert_eq!(
+        a.iter_entities().collect::<Vec<_>>(),
+        b.iter_entities().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        a.registered_components().collect::<Vec<_>>(),
+        b.registered_components().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn insert_or_get_mut_inserts_once() {
+    let mut world = World::new();
+    let player_id = world.create_entity();
+
+    let health = world
+        .insert_or_get_mut(player_id, HealthComponent(10))
+        .unwrap();
+    assert_eq!(10, health.0);
+    health.0 -= 3;
+
+    let health = world
+        .insert_or_get_mut(player_id, HealthComponent(10))
+        .unwrap();
+    assert_eq!(7, health.0);
+    assert_eq!(1, world.component_count::<HealthComponent>());
+}
+
+#[test]
+fn flush_tagged_despawns() {
+    struct PendingDespawn;
+
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..3).map(|_| world.create_entity()).collect();
+    for &entity_id in &ids {
+        world
+            .add_entity_component(entity_id, HealthComponent(1))
+            .unwrap();
+    }
+    world.add_entity_component(ids[0], PendingDespawn).unwrap();
+    world.add_entity_component(ids[2], PendingDespawn).unwrap();
+
+    assert_eq!(2, world.flush_despawns::<PendingDespawn>());
+    assert_eq!(vec![ids[1]], world.iter_entities().collect::<Vec<_>>());
+    assert_eq!(0, world.component_count::<PendingDespawn>());
+    assert_eq!(1, world.component_count::<HealthComponent>());
+}
+
+#[test]
+fn extend_from_bundles() {
+    let mut world = World::new();
+    let enemies = vec![
+        (
+            HealthComponent(1),
+            PositionComponent([1, 0, 0]),
+            VelocityComponent([0, 1, 0]),
+        ),
+        (
+            HealthComponent(2),
+            PositionComponent([2, 0, 0]),
+            VelocityComponent([0, 2, 0]),
+        ),
+        (
+            HealthComponent(3),
+            PositionComponent([3, 0, 0]),
+            VelocityComponent([0, 3, 0]),
+        ),
+    ];
+
+    let ids = world.extend(enemies);
+    assert_eq!(3, ids.len());
+    for (i, &entity_id) in ids.iter().enumerate() {
+        let i = i as i32 + 1;
+        assert_eq!(
+            i,
+            world
+                .get_entity_component::<HealthComponent>(entity_id)
+                .unwrap()
+                .0
+        );
+        assert_eq!(
+            [i, 0, 0],
+            world
+                .get_entity_component::<PositionComponent>(entity_id)
+                .unwrap()
+                .0
+        );
+        assert_eq!(
+            &VelocityComponent([0, i, 0]),
+            world
+                .get_entity_component::<VelocityComponent>(entity_id)
+                .unwrap()
+        );
+    }
+
+    // A type twice can't be added, the entity doesn't stick around.
+    assert!(
+        world
+            .try_spawn((HealthComponent(1), HealthComponent(2)))
+            .is_err()
+    );
+    assert_eq!(3, world.len());
+}
+
+#[test]
+fn for_each_component_of_entity() {
+    let mut world = World::new();
+    let player_id = world.create_entity();
+    let enemy_id = world.create_entity();
+    world.add_entity_component(player_id, PlayerTag).unwrap();
+    world
+        .add_entity_component(player_id, HealthComponent(1))
+        .unwrap();
+    world
+        .add_entity_component(enemy_id, PositionComponent([0, 0, 0]))
+        .unwrap();
+
+    let mut component_ids = Vec::new();
+    world.for_each_component_of(player_id, |component_id| component_ids.push(component_id));
+    assert_eq!(2, component_ids.len());
+    assert!(!component_ids.contains(&ComponentId::of::<PositionComponent>()));
+
+    let mut count = 0;
+    world.for_each_component_of(enemy_id, |_| count += 1);
+    assert_eq!(1, count);
+}
+
+#[test]
+fn position_from_velocity() {
+    let mut world = World::new();
+    let entity_id = world.spawn((PositionComponent([0, 0, 0]), VelocityComponent([1, 2, 3])));
+
+    for _ in 0..2 {
+        let (position, velocity) = world
+            .get_mut_and_ref::<PositionComponent, VelocityComponent>(entity_id)
+            .unwrap();
+        for axis in 0..3 {
+            position.0[axis] += velocity.0[axis];
+        }
+    }
+
+    assert_eq!(
+        [2, 4, 6],
+        world
+            .get_entity_component::<PositionComponent>(entity_id)
+            .unwrap()
+            .0
+    );
+    assert!(
+        world
+            .get_mut_and_ref::<PositionComponent, HealthComponent>(entity_id)
+            .is_err()
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "requested mutably more than once")]
+fn get_mut_and_ref_same_type_panics_in_debug() {
+    let mut world = World::new();
+    let entity_id = world.spawn((HealthComponent(1),));
+
+    let _ = world.get_mut_and_ref::<HealthComponent, HealthComponent>(entity_id);
+}
+
+#[test]
+fn component_order_survives_snapshot_and_clone() {
+    let mut world = World::new();
+    world.register_snapshottable::<VelocityComponent>();
+    world.register_clonable::<VelocityComponent>();
+
+    let ids: Vec<EntityId> = (0..6).map(|_| world.create_entity()).collect();
+    for &entity_id in ids.iter().rev() {
+        world
+            .add_entity_component(entity_id, VelocityComponent([entity_id.index() as i32; 3]))
+            .unwrap();
+    }
+    // Swap-removal scrambles the storage order away from id order.
+    world
+        .remove_entity_component::<VelocityComponent>(ids[4])
+        .unwrap();
+    let order = |world: &World| -> Vec<(EntityId, i32)> {
+        world
+            .component_slice::<VelocityComponent>()
+            .unwrap()
+            .iter()
+            .map(|(entity_id, velocity)| (*entity_id, velocity.0[0]))
+            .collect()
+    };
+    let before = order(&world);
+
+    let snapshot = world.snapshot();
+    world
+        .remove_entity_component::<VelocityComponent>(ids[0])
+        .unwrap();
+    world.restore(&snapshot);
+    assert_eq!(before, order(&world));
+    assert_eq!(before, order(&world.deep_clone()));
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn transfer_component_type_between_worlds() {
+    let mut level = World::new();
+    let a = level.create_entity();
+    let b = level.create_entity();
+    level.add_entity_component(a, HealthComponent(1)).unwrap();
+    level.add_entity_component(b, HealthComponent(2)).unwrap();
+    level.add_entity_component(b, PlayerTag).unwrap();
+
+    let mut streamed = World::new();
+    let new_a = streamed.create_entity();
+    let new_b = streamed.create_entity();
+    let id_map = std::collections::HashMap::from([(a, new_a), (b, new_b)]);
+
+    level.transfer_component_type::<HealthComponent>(&mut streamed, &id_map);
+
+    assert!(level.entities_with::<HealthComponent>().is_empty());
+    // Only the one type moves, the entities and their other components stay.
+    assert!(level.is_entity_valid(b));
+    assert!(level.get_entity_component::<PlayerTag>(b).is_ok());
+    assert!(streamed.entities_with::<PlayerTag>().is_empty());
+
+    assert_eq!(
+        1,
+        streamed
+            .get_entity_component::<HealthComponent>(new_a)
+            .unwrap()
+            .0
+    );
+    assert_eq!(
+        2,
+        streamed
+            .get_entity_component::<HealthComponent>(new_b)
+            .unwrap()
+            .0
+    );
+}
+
+#[test]
+fn validate_entities_mask() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+    world.despawn_entity(b).unwrap();
+
+    let never_created = EntityId::from_raw(100);
+    assert_eq!(
+        vec![true, false, true, false, true],
+        world.validate_entities(&[a, b, c, never_created, a])
+    );
+    assert!(world.validate_entities(&[]).is_empty());
+}
+
+#[test]
+fn strip_entity_keeps_it_valid() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(a, PlayerTag).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+
+    world.strip_entity(a).unwrap();
+
+    assert!(world.is_entity_valid(a));
+    assert!(world.entity_signature(a).unwrap().is_empty());
+    assert_eq!(vec![b], world.entities_with::<HealthComponent>());
+    // A blank entity takes components like a fresh one.
+    world.add_entity_component(a, HealthComponent(3)).unwrap();
+    world.check_consistency().unwrap();
+
+    world.despawn_entity(b).unwrap();
+    assert_eq!(Err(Error::InvalidEntityId(b)), world.strip_entity(b));
+}
+
+/// Thousands of random adds and removes, checking every entity still maps to its own component.
+#[test]
+fn random_add_remove_stays_consistent() {
+    for (soa, dense) in [(false, false), (true, false), (false, true), (true, true)] {
+        let mut world = if dense {
+            World::with_dense_capacity(64)
+        } else {
+            World::new()
+        };
+        if soa {
+            world.enable_soa::<HealthComponent>();
+        }
+        let ids: Vec<EntityId> = (0..64).map(|_| world.create_entity()).collect();
+
+        // xorshift, so the sequence is the same every run.
+        let mut state = 0x2545_f491_u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize
+        };
+
+        for _ in 0..5000 {
+            let entity_id = ids[next() % ids.len()];
+            if world
+                .remove_entity_component::<HealthComponent>(entity_id)
+                .is_err()
+            {
+                world
+                    .add_entity_component(entity_id, HealthComponent(entity_id.index() as i32))
+                    .unwrap();
+            }
+        }
+
+        world.check_consistency().unwrap();
+        for entity_id in world.entities_with::<HealthComponent>() {
+            assert_eq!(
+                entity_id.index() as i32,
+                world
+                    .get_entity_component::<HealthComponent>(entity_id)
+                    .unwrap()
+                    .0
+            );
+        }
+    }
+}
+
+#[test]
+fn snapshot_ids_then_despawn() {
+    let mut world = World::new();
+    for health in [0, 5, 0, 7, 0] {
+        let entity_id = world.create_entity();
+        world
+            .add_entity_component(entity_id, HealthComponent(health))
+            .unwrap();
+    }
+
+    for entity_id in world.component_ids_snapshot::<HealthComponent>() {
+        let Ok(health) = world.get_entity_component::<HealthComponent>(entity_id) else {
+            continue;
+        };
+        if health.0 == 0 {
+            world.despawn_entity(entity_id).unwrap();
+        }
+    }
+
+    assert_eq!(2, world.len());
+    assert_eq!(2, world.component_ids_snapshot::<HealthComponent>().len());
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn remove_if_present_twice() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    world.add_entity_component(a, HealthComponent(4)).unwrap();
+
+    assert_eq!(
+        4,
+        world
+            .remove_entity_component_if_present::<HealthComponent>(a)
+            .unwrap()
+            .0
+    );
+    assert!(
+        world
+            .remove_entity_component_if_present::<HealthComponent>(a)
+            .is_none()
+    );
+    assert!(
+        world
+            .remove_entity_component_if_present::<PlayerTag>(a)
+            .is_none()
+    );
+    world.despawn_entity(a).unwrap();
+    assert!(
+        world
+            .remove_entity_component_if_present::<HealthComponent>(a)
+            .is_none()
+    );
+}
+
+#[test]
+fn accessor_iter_is_exact_size_and_reversible() {
+    for soa in [false, true] {
+        let mut world = World::new();
+        if soa {
+            world.enable_soa::<HealthComponent>();
+        }
+        for health in 0..4 {
+            let entity_id = world.create_entity();
+            world
+                .add_entity_component(entity_id, HealthComponent(health))
+                .unwrap();
+        }
+
+        let accessor = world.component_accessor::<HealthComponent>().unwrap();
+        let mut iter = accessor.iter();
+        assert_eq!(4, iter.len());
+        iter.next();
+        assert_eq!(3, iter.len());
+
+        let reversed: Vec<i32> = accessor.iter().rev().map(|(_, health)| health.0).collect();
+        assert_eq!(vec![3, 2, 1, 0], reversed);
+    }
+}
+
+#[test]
+fn component_type_of_boxed_component() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+
+    let boxed: Box<dyn std::any::Any> = Box::new(HealthComponent(2));
+    assert_eq!(
+        Some(ComponentId::of::<HealthComponent>()),
+        world.component_type_of(&*boxed)
+    );
+    // Never registered.
+    assert_eq!(None, world.component_type_of(&PlayerTag));
+    assert_eq!(None, world.component_type_of(&boxed));
+}
+
+#[test]
+fn despawn_reparent_moves_children_up() {
+    let mut world = World::new();
+
+    let root = world.create_entity();
+    let middle = world.create_entity();
+    let sibling = world.create_entity();
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.set_parent(middle, root).unwrap();
+    world.set_parent(sibling, root).unwrap();
+    world.set_parent(a, middle).unwrap();
+    world.set_parent(b, middle).unwrap();
+
+    world.despawn_reparent(middle).unwrap();
+
+    assert!(!world.is_entity_valid(middle));
+    assert_eq!(Some(root), world.parent_of(a));
+    assert_eq!(Some(root), world.parent_of(b));
+    assert_eq!(
+        &[sibling, a, b],
+        world.get_entity_component::<Children>(root).unwrap().ids()
+    );
+
+    // Children of a root become roots.
+    world.despawn_reparent(root).unwrap();
+    for entity_id in [sibling, a, b] {
+        assert_eq!(None, world.parent_of(entity_id));
+    }
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn spawn_batch_grid() {
+    let mut world = World::new();
+
+    let entity_ids = world.spawn_batch(9, |i| {
+        (PositionComponent([(i % 3) as i32, (i / 3) as i32, 0]),)
+    });
+
+    assert_eq!(9, entity_ids.len());
+    assert_eq!(9, world.len());
+    for (i, &entity_id) in entity_ids.iter().enumerate() {
+        let position = world
+            .get_entity_component::<PositionComponent>(entity_id)
+            .unwrap();
+        assert_eq!([(i % 3) as i32, (i / 3) as i32, 0], position.0);
+    }
+}
+
+#[test]
+fn for_each_with_peers_sums_neighbours() {
+    for soa in [false, true] {
+        let mut world = World::new();
+        if soa {
+            world.enable_soa::<PositionComponent>();
+        }
+        let ids: Vec<EntityId> = (1..=4)
+            .map(|x| world.spawn((PositionComponent([x, 0, 0]),)))
+            .collect();
+        world.set_active(ids[3], false).unwrap();
+
+        // Only y is written, so every entity sees the same x values.
+        world.for_each_with_peers::<PositionComponent>(|entity_id, position, peers| {
+            assert!(peers.get(entity_id).is_none());
+            position.0[1] = peers.iter().map(|(_, peer)| peer.0[0]).sum();
+        });
+
+        for (entity_id, expected) in ids.iter().zip([5, 4, 3]) {
+            assert_eq!(
+                expected,
+                world
+                    .get_entity_component::<PositionComponent>(*entity_id)
+                    .unwrap()
+                    .0[1]
+            );
+        }
+        // Inactive entities are neither visited nor seen as peers.
+        assert_eq!(
+            0,
+            world
+                .get_entity_component::<PositionComponent>(ids[3])
+                .unwrap()
+                .0[1]
+        );
+
+        world.for_each_with_peers::<PositionComponent>(|entity_id, _, peers| {
+            if entity_id == ids[0] {
+                assert_eq!(2, peers.get(ids[1]).unwrap().0[0]);
+                assert!(peers.get(ids[3]).is_none());
+            }
+        });
+    }
+}
+
+#[test]
+fn try_add_reserves_fallibly() {
+    let mut world = World::new();
+    let a = world.create_entity();
+
+    world
+        .try_add_entity_component(a, HealthComponent(3))
+        .unwrap();
+    assert_eq!(
+        3,
+        world.get_entity_component::<HealthComponent>(a).unwrap().0
+    );
+    assert_eq!(
+        Err(Error::ComponentAlreadyAdded(
+            std::any::type_name::<HealthComponent>(),
+            a
+        )),
+        world.try_add_entity_component(a, HealthComponent(4))
+    );
+
+    // More than can ever be allocated, so the failure path runs without a real OOM.
+    assert_eq!(
+        Err(Error::AllocationFailed(std::any::type_name::<
+            HealthComponent,
+        >())),
+        world.try_reserve_components::<HealthComponent>(usize::MAX)
+    );
+    world.check_consistency().unwrap();
+}
+
+/// Joins only go through the storage trait, so every backend and layout gives the same result.
+#[test]
+fn query2_same_on_every_storage_kind() {
+    let setups: [fn(&mut World); 4] = [
+        |_| {},
+        |world| world.enable_soa::<HealthComponent>(),
+        |world| {
+            world.enable_bitset::<HealthComponent>();
+            world.enable_bitset::<PositionComponent>();
+        },
+        |world| {
+            world.enable_soa::<PositionComponent>();
+            world.enable_bitset::<HealthComponent>();
+        },
+    ];
+
+    for setup in setups {
+        let mut world = World::new();
+        setup(&mut world);
+        for i in 0..10 {
+            let entity_id = world.create_entity();
+            if i % 2 == 0 {
+                world
+                    .add_entity_component(entity_id, HealthComponent(i))
+                    .unwrap();
+            }
+            if i % 3 == 0 {
+                world
+                    .add_entity_component(entity_id, PositionComponent([i, 0, 0]))
+                    .unwrap();
+            }
+        }
+
+        let mut joined: Vec<(i32, i32)> = world
+            .query2::<HealthComponent, PositionComponent>()
+            .map(|(_, health, position)| (health.0, position.0[0]))
+            .collect();
+        joined.sort();
+        assert_eq!(vec![(0, 0), (6, 6)], joined);
+        assert_eq!(
+            2,
+            world.count_query2::<HealthComponent, PositionComponent>()
+        );
+    }
+}
+
+#[test]
+fn checkpoint_ring_rolls_back() {
+    let mut world = World::new();
+    world.register_snapshottable::<VelocityComponent>();
+    let a = world.create_entity();
+    world
+        .add_entity_component(a, VelocityComponent([0; 3]))
+        .unwrap();
+
+    let mut ring = CheckpointRing::new(3);
+    for frame in 1..=3 {
+        world
+            .get_entity_component_mut::<VelocityComponent>(a)
+            .unwrap()
+            .0 = [frame; 3];
+        ring.push_checkpoint(&world);
+    }
+    world.despawn_entity(a).unwrap();
+
+    assert!(!ring.rollback_to(&mut world, 3));
+    assert!(ring.rollback_to(&mut world, 2));
+    assert_eq!(
+        Ok(&VelocityComponent([1; 3])),
+        world.get_entity_component::<VelocityComponent>(a)
+    );
+    // The two newer checkpoints were dropped with the rollback.
+    assert_eq!(1, ring.len());
+
+    // Full rings overwrite the oldest.
+    for _ in 0..5 {
+        ring.push_checkpoint(&world);
+    }
+    assert_eq!(ring.capacity(), ring.len());
+}
+
+#[test]
+fn tracked_mut_records_only_writes() {
+    let mut world = World::new();
+    world.track_changes::<HealthComponent>();
+    let a = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.drain_changes::<HealthComponent>();
+
+    {
+        let health = world
+            .get_entity_component_tracked_mut::<HealthComponent>(a)
+            .unwrap();
+        assert_eq!(1, health.0);
+    }
+    assert!(world.drain_changes::<HealthComponent>().is_empty());
+
+    {
+        let mut health = world
+            .get_entity_component_tracked_mut::<HealthComponent>(a)
+            .unwrap();
+        health.0 += 1;
+        health.0 += 1;
+    }
+    // Once per borrow, not per write.
+    assert_eq!(
+        vec![(a, ChangeKind::Modified)],
+        world.drain_changes::<HealthComponent>()
+    );
+    assert_eq!(
+        3,
+        world.get_entity_component::<HealthComponent>(a).unwrap().0
+    );
+}
+
+#[test]
+fn component_set_histogram_counts_signatures() {
+    let mut world = World::new();
+    let players: Vec<EntityId> = (0..3)
+        .map(|_| world.spawn((HealthComponent(1), PlayerTag)))
+        .collect();
+    world.spawn((HealthComponent(1),));
+    world.create_entity();
+
+    let histogram = world.component_set_histogram();
+
+    assert_eq!(3, histogram.len());
+    assert_eq!(3, histogram[&world.entity_signature(players[0]).unwrap()]);
+    assert_eq!(1, histogram[&vec![ComponentId::of::<HealthComponent>()]]);
+    assert_eq!(1, histogram[&Vec::new()]);
+}
+
+#[test]
+fn entity_hooks_see_creations_and_despawns() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut world = World::new();
+
+    let created = Rc::new(RefCell::new(Vec::new()));
+    let despawned = Rc::new(RefCell::new(Vec::new()));
+    {
+        let created = created.clone();
+        world.on_entity_created(move |entity_id| created.borrow_mut().push(entity_id));
+        let despawned = despawned.clone();
+        world.on_entity_despawned(move |entity_id| despawned.borrow_mut().push(entity_id));
+    }
+
+    let a = world.create_entity();
+    let batch = world.spawn_batch(2, |i| (HealthComponent(i as i32),));
+    let picked = EntityId::from_raw(50);
+    world.create_entity_with_id(picked).unwrap();
+    // Failed creations don't count.
+    assert!(world.create_entity_with_id(picked).is_err());
+
+    world.despawn_entity(batch[0]).unwrap();
+    world.despawn_entity(a).unwrap();
+    assert!(world.despawn_entity(a).is_err());
+
+    assert_eq!(vec![a, batch[0], batch[1], picked], *created.borrow());
+    assert_eq!(vec![batch[0], a], *despawned.borrow());
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Label(String);
+
+#[test]
+fn clone_entity_copies_clonable_components() {
+    let mut world = World::new();
+    world.register_clonable::<VelocityComponent>();
+    world.register_clonable::<Label>();
+
+    let prefab = world.create_entity();
+    world
+        .add_entity_component(prefab, VelocityComponent([1, 2, 3]))
+        .unwrap();
+    world
+        .add_entity_component(prefab, Label("orc".to_string()))
+        .unwrap();
+    world.add_entity_component(prefab, PlayerTag).unwrap();
+
+    let copy = world.clone_entity(prefab).unwrap();
+    assert_ne!(prefab, copy);
+    world
+        .get_entity_component_mut::<Label>(copy)
+        .unwrap()
+        .0
+        .push_str(" chief");
+
+    assert_eq!(
+        Ok(&Label("orc".to_string())),
+        world.get_entity_component::<Label>(prefab)
+    );
+    assert_eq!(
+        Ok(&Label("orc chief".to_string())),
+        world.get_entity_component::<Label>(copy)
+    );
+    assert_eq!(
+        Ok(&VelocityComponent([1, 2, 3])),
+        world.get_entity_component::<VelocityComponent>(copy)
+    );
+    // Not clonable, skipped.
+    assert!(world.get_entity_component::<PlayerTag>(copy).is_err());
+}
+
+#[test]
+#[should_panic(expected = "was never registered as clonable")]
+fn clone_entity_with_panic_policy() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    world.add_entity_component(a, PlayerTag).unwrap();
+
+    let _ = world.clone_entity_with(a, UnclonablePolicy::Panic);
+}
+
+#[test]
+fn for_each_pair_yields_each_pair_once() {
+    let mut world = World::new();
+    let ids = world.spawn_batch(5, |i| (HealthComponent(i as i32),));
+    world.set_active(ids[4], false).unwrap();
+
+    let mut pairs = Vec::new();
+    world.for_each_pair::<HealthComponent, _>(|a, _, b, _| pairs.push((a, b)));
+
+    assert_eq!(6, pairs.len());
+    let unique: HashSet<(EntityId, EntityId)> =
+        pairs.iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+    assert_eq!(6, unique.len());
+    assert!(pairs.iter().all(|&(a, b)| a != ids[4] && b != ids[4]));
+}
+
+#[test]
+fn shim_registration_predicates() {
+    let mut world = World::new();
+    assert!(!world.is_snapshottable::<VelocityComponent>());
+    assert!(!world.is_clonable::<VelocityComponent>());
+    assert!(!world.is_debuggable::<VelocityComponent>());
+
+    world.register_snapshottable::<VelocityComponent>();
+    assert!(world.is_snapshottable::<VelocityComponent>());
+    assert!(!world.is_clonable::<VelocityComponent>());
+
+    world.register_clonable::<VelocityComponent>();
+    world.register_debuggable::<VelocityComponent>();
+    assert!(world.is_clonable::<VelocityComponent>());
+    assert!(world.is_debuggable::<VelocityComponent>());
+
+    // Adding a component alone registers no shims.
+    let a = world.create_entity();
+    world
+        .add_entity_component(a, Label("a".to_string()))
+        .unwrap();
+    assert!(!world.is_snapshottable::<Label>());
+    assert!(!world.is_clonable::<Label>());
+    assert!(!world.is_debuggable::<Label>());
+}
+
+#[test]
+fn compact_ids_drops_stale_ids() {
+    let mut world = World::new();
+    let stale = world.create_entity();
+    let kept = world.create_entity();
+    world
+        .add_entity_component(kept, Name("kept".to_string()))
+        .unwrap();
+    world.set_active(kept, false).unwrap();
+    world.despawn_entity(stale).unwrap();
+    // Bookkeeping that missed the despawn, compacting shouldn't trust it.
+    world.inactive_set.insert(stale);
+    world.name_index.insert("stale".to_string(), vec![stale]);
+
+    let id_map = world.compact_ids();
+
+    let kept = id_map[&kept];
+    assert_eq!(Some(kept), world.find_by_name("kept"));
+    assert_eq!(None, world.find_by_name("stale"));
+    assert_eq!(
+        vec![kept],
+        world.inactive_set.iter().copied().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn compact_ids_renumbers_everything() {
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..10).map(|_| world.create_entity()).collect();
+    let (parent, child, loner) = (ids[3], ids[7], ids[9]);
+    for (i, &entity_id) in ids.iter().enumerate() {
+        if ![parent, child, loner].contains(&entity_id) {
+            world.despawn_entity(entity_id).unwrap();
+            continue;
+        }
+        world
+            .add_entity_component(entity_id, HealthComponent(i as i32))
+            .unwrap();
+    }
+    world.enable_bitset::<HealthComponent>();
+    world.set_parent(child, parent).unwrap();
+    world
+        .add_entity_component(loner, Name("loner".to_string()))
+        .unwrap();
+    world.set_active(loner, false).unwrap();
+
+    let id_map = world.compact_ids();
+
+    assert_eq!(3, id_map.len());
+    let (parent, child, loner) = (id_map[&parent], id_map[&child], id_map[&loner]);
+    assert_eq!(
+        vec![
+            EntityId::from_raw_parts(0, 1),
+            EntityId::from_raw_parts(1, 1),
+            EntityId::from_raw_parts(2, 1)
+        ],
+        vec![parent, child, loner]
+    );
+    for (entity_id, health) in [(parent, 3), (child, 7), (loner, 9)] {
+        assert_eq!(
+            health,
+            world
+                .get_entity_component::<HealthComponent>(entity_id)
+                .unwrap()
+                .0
+        );
+    }
+    assert_eq!(Some(parent), world.parent_of(child));
+    assert_eq!(Some(loner), world.find_by_name("loner"));
+    assert!(!world.is_entity_active(loner));
+    world.check_consistency().unwrap();
+
+    assert_eq!(EntityId::from_raw_parts(3, 1), world.create_entity());
+}
+
+#[test]
+fn retain_entities_despawns_rejected() {
+    let mut world = World::new();
+    let ids = world.spawn_batch(6, |i| (HealthComponent(i as i32),));
+    world.set_active(ids[1], false).unwrap();
+
+    let removed = world.retain_entities(|entity_id| entity_id.index() % 2 == 0);
+
+    assert_eq!(3, removed);
+    assert_eq!(
+        vec![ids[0], ids[2], ids[4]],
+        world.iter_entities_sorted().collect::<Vec<_>>()
+    );
+    assert_eq!(3, world.entities_with::<HealthComponent>().len());
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn register_up_front() {
+    let mut world = World::new();
+    let a = world.create_entity();
+    assert_eq!(
+        Err(Error::InvalidWorldComponent(std::any::type_name::<
+            VelocityComponent,
+        >())),
+        world.get_entity_component::<VelocityComponent>(a)
+    );
+
+    world
+        .register::<VelocityComponent>()
+        .clonable()
+        .snapshottable()
+        .debuggable();
+
+    assert_eq!(
+        Err(Error::InvalidEntityComponent(
+            std::any::type_name::<VelocityComponent>(),
+            a
+        )),
+        world.get_entity_component::<VelocityComponent>(a)
+    );
+    assert!(world.is_clonable::<VelocityComponent>());
+    assert!(world.is_snapshottable::<VelocityComponent>());
+    assert!(world.is_debuggable::<VelocityComponent>());
+
+    world
+        .add_entity_component(a, VelocityComponent([1; 3]))
+        .unwrap();
+    // Registering again keeps what's stored.
+    world.register::<VelocityComponent>();
+    assert!(world.get_entity_component::<VelocityComponent>(a).is_ok());
+}
+
+#[test]
+fn diff_against_mutated_copy() {
+    let mut world = World::new();
+    world.register::<VelocityComponent>().clonable();
+    world.register::<Label>().clonable();
+    world.register::<PlayerTag>();
+    let a = world.spawn((VelocityComponent([0; 3]),));
+    let b = world.spawn((VelocityComponent([0; 3]),));
+    let c = world.spawn((VelocityComponent([0; 3]), Label("c".to_string())));
+
+    let mut registry = TypeRegistry::new();
+    assert!(world.diff(&world.deep_clone(), &registry).is_empty());
+
+    let mut copy = world.deep_clone();
+    copy.despawn_entity(b).unwrap();
+    let d = copy.create_entity();
+    copy.add_entity_component(a, PlayerTag).unwrap();
+    copy.remove_entity_component::<Label>(c).unwrap();
+    copy.get_entity_component_mut::<VelocityComponent>(a)
+        .unwrap()
+        .0 = [1; 3];
+
+    let diff = world.diff(&copy, &registry);
+    assert_eq!(vec![b], diff.only_in_self);
+    assert_eq!(vec![d], diff.only_in_other);
+    assert_eq!(vec![(a, ComponentId::of::<PlayerTag>())], diff.added);
+    assert_eq!(vec![(c, ComponentId::of::<Label>())], diff.removed);
+    // Values are only compared for registered types.
+    assert!(diff.changed.is_empty());
+
+    registry.register_comparable::<VelocityComponent>();
+    assert_eq!(
+        vec![(a, ComponentId::of::<VelocityComponent>())],
+        world.diff(&copy, &registry).changed
+    );
+}
+
+#[test]
+fn dense_world_matches_sparse() {
+    let mut world = World::with_dense_capacity(4);
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+    world.add_entity_component(b, PlayerTag).unwrap();
+
+    let joined: Vec<(EntityId, i32)> = world
+        .query2::<HealthComponent, PlayerTag>()
+        .map(|(entity_id, health, _)| (entity_id, health.0))
+        .collect();
+    assert_eq!(vec![(b, 2)], joined);
+    world.remove_entity_component::<HealthComponent>(a).unwrap();
+    assert_eq!(vec![b], world.entities_with::<HealthComponent>());
+    world.despawn_entity(b).unwrap();
+    assert!(world.entities_with::<PlayerTag>().is_empty());
+    world.check_consistency().unwrap();
+
+    world.create_entity();
+    world.create_entity_with_id(EntityId::from_raw(3)).unwrap();
+    assert_eq!(Err(Error::OutOfEntityIds), world.try_create_entity());
+    assert_eq!(
+        Err(Error::OutOfEntityIds),
+        world.create_entity_with_id(EntityId::from_raw(4))
+    );
+
+    // Despawning frees room for one more.
+    world.despawn_entity(a).unwrap();
+    let recycled = world.try_create_entity().unwrap();
+    assert_eq!(a.index(), recycled.index());
+    assert!(!world.is_entity_valid(a));
+    assert_eq!(Err(Error::OutOfEntityIds), world.try_create_entity());
+}
+
+#[test]
+fn destroy_entity_purges_every_storage() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(a, PlayerTag).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+
+    world.destroy_entity(a).unwrap();
+
+    assert_eq!(
+        Err(Error::InvalidEntityId(a)),
+        world.get_entity_component::<HealthComponent>(a).map(|_| ())
+    );
+    assert_eq!(vec![b], world.entities_with::<HealthComponent>());
+    assert!(world.entities_with::<PlayerTag>().is_empty());
+    assert_eq!(Err(Error::InvalidEntityId(a)), world.destroy_entity(a));
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn stale_ids_stay_invalid_after_reuse() {
+    for dense in [false, true] {
+        let mut world = if dense {
+            World::with_dense_capacity(8)
+        } else {
+            World::new()
+        };
+        world.enable_bitset::<HealthComponent>();
+        world.enable_bitset::<PlayerTag>();
+
+        let stale = world.create_entity();
+        let kept = world.create_entity();
+        world
+            .add_entity_component(stale, HealthComponent(1))
+            .unwrap();
+        world.despawn_entity(stale).unwrap();
+        let kept = world.compact_ids()[&kept];
+
+        // The index of `stale` now belongs to `kept`, under a new generation.
+        assert_eq!(EntityId::from_raw_parts(stale.index(), 1), kept);
+        world
+            .add_entity_component(kept, HealthComponent(2))
+            .unwrap();
+        world.add_entity_component(kept, PlayerTag).unwrap();
+        assert!(world.is_entity_valid(kept));
+        assert!(!world.is_entity_valid(stale));
+        assert_eq!(
+            Err(Error::InvalidEntityId(stale)),
+            world
+                .get_entity_component::<HealthComponent>(stale)
+                .map(|_| ())
+        );
+        assert_eq!(1, world.query2::<HealthComponent, PlayerTag>().count());
+
+        let fresh = world.create_entity();
+        assert!(fresh.allocated_after(kept));
+        assert!(kept.allocated_after(stale));
+        assert_eq!("1v1", fresh.to_string());
+        world.check_consistency().unwrap();
+    }
+}
+
+#[test]
+fn despawned_indices_are_recycled() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.destroy_entity(a).unwrap();
+
+    let c = world.create_entity();
+    assert_eq!(a.index(), c.index());
+    assert_ne!(a, c);
+    assert!(!world.is_entity_valid(a));
+    assert_eq!(
+        Err(Error::InvalidEntityId(a)),
+        world.get_entity_component::<HealthComponent>(a).map(|_| ())
+    );
+    assert!(world.get_entity_component::<HealthComponent>(c).is_err());
+
+    // Only once the free indices run out does the counter grow.
+    world.destroy_entity(c).unwrap();
+    world.destroy_entity(b).unwrap();
+    let reused: HashSet<usize> = (0..2).map(|_| world.create_entity().index()).collect();
+    assert_eq!(HashSet::from([a.index(), b.index()]), reused);
+    assert_eq!(2, world.create_entity().index());
+    assert_eq!(3, world.len());
+}
+
+#[test]
+fn has_component_checks_without_borrowing() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world.add_entity_component(a, HealthComponent(1)).unwrap();
+    world.add_entity_component(a, PlayerTag).unwrap();
+    world.add_entity_component(b, HealthComponent(2)).unwrap();
+
+    assert!(world.has_component::<HealthComponent>(a));
+    assert!(!world.has_component::<PlayerTag>(b));
+    assert!(!world.has_component::<VelocityComponent>(a));
+    let both = [
+        ComponentId::of::<HealthComponent>(),
+        ComponentId::of::<PlayerTag>(),
+    ];
+    assert!(world.has_components(a, &both));
+    assert!(!world.has_components(b, &both));
+    assert!(world.has_components(b, &[]));
+
+    world.set_active(a, false).unwrap();
+    assert!(world.has_component::<HealthComponent>(a));
+    world.despawn_entity(a).unwrap();
+    assert!(!world.has_component::<HealthComponent>(a));
+    assert!(!world.has_components(a, &[]));
+}
+
+#[test]
+fn set_entity_component_upserts() {
+    let mut world = World::new();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    world
+        .add_entity_component(b, VelocityComponent([0; 3]))
+        .unwrap();
+
+    assert_eq!(
+        Ok(None),
+        world.set_entity_component(a, VelocityComponent([1; 3]))
+    );
+    assert_eq!(
+        Ok(Some(VelocityComponent([1; 3]))),
+        world.set_entity_component(a, VelocityComponent([2; 3]))
+    );
+    assert_eq!(
+        Ok(&VelocityComponent([2; 3])),
+        world.get_entity_component::<VelocityComponent>(a)
+    );
+    // Overwriting happens in place, nothing moved.
+    assert_eq!(vec![b, a], world.entities_with::<VelocityComponent>());
+
+    world.despawn_entity(b).unwrap();
+    assert_eq!(
+        Err(Error::InvalidEntityId(b)),
+        world.set_entity_component(b, VelocityComponent([3; 3]))
+    );
+}
+
+#[test]
+fn iter_component_skips_inactive() {
+    let mut world = World::new();
+    assert_eq!(0, world.iter_component::<HealthComponent>().count());
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+    for (entity_id, health) in [(a, 1), (b, 2), (c, 3)] {
+        world
+            .add_entity_component(entity_id, HealthComponent(health))
+            .unwrap();
+    }
+    world.set_active(b, false).unwrap();
+
+    let healths: Vec<(EntityId, i32)> = world
+        .iter_component::<HealthComponent>()
+        .map(|(entity_id, health)| (entity_id, health.0))
+        .collect();
+    assert_eq!(vec![(a, 1), (c, 3)], healths);
+}
+
+#[test]
+fn iter_component_mut_moves_everything() {
+    let mut world = World::new();
+    assert_eq!(0, world.iter_component_mut::<PositionComponent>().count());
+    world.track_changes::<PositionComponent>();
+
+    let a = world.create_entity();
+    let b = world.create_entity();
+    let c = world.create_entity();
+    for entity_id in [a, b, c] {
+        world
+            .add_entity_component(entity_id, PositionComponent([0; 3]))
+            .unwrap();
+    }
+    world.set_active(c, false).unwrap();
+    world.drain_changes::<PositionComponent>();
+
+    for (_, position) in world.iter_component_mut::<PositionComponent>() {
+        position.0[0] += 1;
+    }
+
+    let xs: Vec<i32> = [a, b, c]
+        .iter()
+        .map(|&entity_id| {
+            world
+                .get_entity_component::<PositionComponent>(entity_id)
+                .unwrap()
+                .0[0]
+        })
+        .collect();
+    assert_eq!(vec![1, 1, 0], xs);
+    assert_eq!(
+        vec![(a, ChangeKind::Modified), (b, ChangeKind::Modified)],
+        world.drain_changes::<PositionComponent>()
+    );
+}
+
+#[test]
+fn hierarchy_is_cloned_and_snapshotted() {
+    let mut world = World::new();
+    let parent = world.create_entity();
+    let child = world.create_entity();
+    world.set_parent(child, parent).unwrap();
+
+    let copy = world.deep_clone_with(UnclonablePolicy::Panic);
+    assert_eq!(Some(parent), copy.parent_of(child));
+    assert_eq!(
+        Ok(&Children(vec![child])),
+        copy.get_entity_component::<Children>(parent)
+    );
+    copy.check_consistency().unwrap();
+
+    let snapshot = world.snapshot();
+    world.remove_parent(child).unwrap();
+    world.restore(&snapshot);
+    assert_eq!(Some(parent), world.parent_of(child));
+
+    // A copy of the child can't be another child of the same parent behind its back.
+    let child_copy = world
+        .clone_entity_with(child, UnclonablePolicy::Panic)
+        .unwrap();
+    assert_eq!(None, world.parent_of(child_copy));
+    assert_eq!(
+        vec![child],
+        world
+            .get_entity_component::<Children>(parent)
+            .unwrap()
+            .ids()
+    );
+}
+
+#[test]
+fn restore_replays_the_same_ids() {
+    let mut world = World::new();
+    let doomed: Vec<EntityId> = (0..3).map(|_| world.create_entity()).collect();
+    let snapshot = world.snapshot();
+
+    let replay = |world: &mut World| {
+        for &entity_id in &doomed {
+            world.despawn_entity(entity_id).unwrap();
+        }
+        (0..4).map(|_| world.create_entity()).collect::<Vec<_>>()
+    };
+    let first_run = replay(&mut world);
+    world.restore(&snapshot);
+    let second_run = replay(&mut world);
+
+    assert_eq!(first_run, second_run);
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn restore_forgets_names_of_dropped_entities() {
+    let mut world = World::new();
+    let kept = world.create_entity();
+    world
+        .add_entity_component(kept, Name("kept".to_string()))
+        .unwrap();
+    let snapshot = world.snapshot();
+
+    let dropped = world.create_entity();
+    world
+        .add_entity_component(dropped, Name("dropped".to_string()))
+        .unwrap();
+    world.restore(&snapshot);
+
+    assert_eq!(None, world.find_by_name("dropped"));
+    assert_eq!(Some(kept), world.find_by_name("kept"));
+    let id_map = world.compact_ids();
+    assert_eq!(Some(id_map[&kept]), world.find_by_name("kept"));
+}
+
+#[test]
+fn restore_prunes_dropped_children() {
+    let mut world = World::new();
+    let parent = world.create_entity();
+    let kept = world.create_entity();
+    world.set_parent(kept, parent).unwrap();
+    let snapshot = world.snapshot();
+
+    let dropped = world.create_entity();
+    world.set_parent(dropped, parent).unwrap();
+    let orphan_parent = world.create_entity();
+    world.set_parent(parent, orphan_parent).unwrap();
+    world.restore(&snapshot);
+
+    assert_eq!(
+        vec![kept],
+        world
+            .get_entity_component::<Children>(parent)
+            .unwrap()
+            .ids()
+    );
+    assert_eq!(None, world.parent_of(parent));
+    world.check_consistency().unwrap();
+
+    world.compact_ids();
+    let parent = world.find_entity::<Children, _>(|_| true).unwrap();
+    world.despawn_recursive(parent).unwrap();
+    assert!(world.is_empty());
+}
+
+#[test]
+fn merge_remaps_hierarchy() {
+    let mut world = World::new();
+    world.create_entity();
+
+    let mut other = World::new();
+    let parent = other.create_entity();
+    let child = other.create_entity();
+    other.set_parent(child, parent).unwrap();
+
+    let mut registry = TypeRegistry::new();
+    registry.register::<Parent>().register::<Children>();
+    let id_map = world.merge(other, &registry);
+
+    let (parent, child) = (id_map[&parent], id_map[&child]);
+    assert_eq!(Some(parent), world.parent_of(child));
+    assert_eq!(
+        vec![child],
+        world
+            .get_entity_component::<Children>(parent)
+            .unwrap()
+            .ids()
+    );
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn try_merge_into_full_dense_world() {
+    let mut world = World::with_dense_capacity(2);
+    let existing = world.create_entity();
+
+    let mut other = World::new();
+    for _ in 0..2 {
+        let entity_id = other.create_entity();
+        other
+            .add_entity_component(entity_id, HealthComponent(1))
+            .unwrap();
+    }
+    let mut registry = TypeRegistry::new();
+    registry.register::<HealthComponent>();
+
+    assert_eq!(
+        Err(Error::OutOfEntityIds),
+        world.try_merge(other, &registry)
+    );
+    assert_eq!(vec![existing], world.iter_entities().collect::<Vec<_>>());
+    assert!(world.entities_with::<HealthComponent>().is_empty());
+}
+
+#[test]
+fn generic_removal_keeps_the_hierarchy_linked() {
+    let mut world = World::new();
+    let parent = world.create_entity();
+    let children: Vec<EntityId> = (0..3).map(|_| world.create_entity()).collect();
+    for &child in &children {
+        world.set_parent(child, parent).unwrap();
+    }
+    let child_ids = |world: &World| {
+        world
+            .get_entity_component::<Children>(parent)
+            .map(|children| children.ids().to_vec())
+            .unwrap_or_default()
+    };
+
+    assert_eq!(
+        Ok(Parent(parent)),
+        world.remove_entity_component::<Parent>(children[0])
+    );
+    assert_eq!(vec![children[1], children[2]], child_ids(&world));
+    assert_eq!(
+        Some(Parent(parent)),
+        world.take_component::<Parent>(children[1])
+    );
+    world
+        .remove_entity_component_stable::<Parent>(children[2])
+        .unwrap();
+    assert!(!world.has_component::<Children>(parent));
+
+    for &child in &children {
+        world.set_parent(child, parent).unwrap();
+    }
+    world.remove_entity_component::<Children>(parent).unwrap();
+    assert!(
+        children
+            .iter()
+            .all(|&child| world.parent_of(child).is_none())
+    );
+
+    for &child in &children {
+        world.set_parent(child, parent).unwrap();
+    }
+    world.clear_component::<Parent>();
+    assert!(child_ids(&world).is_empty());
+
+    for &child in &children {
+        world.set_parent(child, parent).unwrap();
+    }
+    assert_eq!(1, world.drain_components::<Children>().count());
+    assert!(
+        children
+            .iter()
+            .all(|&child| world.parent_of(child).is_none())
+    );
+
+    world.set_parent(children[0], parent).unwrap();
+    world.set_parent(children[1], children[2]).unwrap();
+    assert_eq!(
+        Err(Error::HierarchyComponent(std::any::type_name::<Parent>())),
+        world.swap_components::<Parent>(children[0], children[1])
+    );
+    assert_eq!(Some(parent), world.parent_of(children[0]));
+    world.check_consistency().unwrap();
+}
+
+#[test]
+fn inactive_entities_are_skipped_by_lookups_and_map_all() {
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..4).map(|_| world.create_entity()).collect();
+    for (health, &entity_id) in ids.iter().enumerate() {
+        world
+            .add_entity_component(entity_id, HealthComponent(health as i32))
+            .unwrap();
+    }
+    world.set_active(ids[0], false).unwrap();
+    world.set_active(ids[3], false).unwrap();
+
+    assert_eq!(
+        None,
+        world.find_entity::<HealthComponent, _>(|health| health.0 == 0)
+    );
+    assert_eq!(
+        Some(ids[1]),
+        world
+            .view()
+            .find_entity::<HealthComponent, _>(|health| health.0 < 2)
+    );
+
+    {
+        let accessor = world.component_accessor::<HealthComponent>().unwrap();
+        assert_eq!(2, accessor.len());
+        let mut iter = accessor.iter();
+        assert_eq!(2, iter.len());
+        assert_eq!(Some(2), iter.next_back().map(|(_, health)| health.0));
+        assert_eq!(1, iter.len());
+        assert_eq!(Some(ids[1]), iter.next().map(|(entity_id, _)| entity_id));
+        assert!(iter.next().is_none());
+        assert_eq!(Some(0), accessor.get(ids[0]).map(|health| health.0));
+    }
+
+    world.map_all::<HealthComponent, _>(|_, health| {
+        health.0 += 10;
+        std::ops::ControlFlow::Continue(())
+    });
+    let healths: Vec<i32> = ids
+        .iter()
+        .map(|&entity_id| {
+            world
+                .get_entity_component::<HealthComponent>(entity_id)
+                .unwrap()
+                .0
+        })
+        .collect();
+    assert_eq!(vec![0, 11, 12, 3], healths);
+}
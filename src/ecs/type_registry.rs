@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use super::error::Error;
+use super::id_types::{ComponentId, EntityId};
+use super::name::Name;
+use super::world::World;
+
+/// Moves every `C` from one world into another, under the ids given by the map.
+type MoveComponentsFn = fn(&mut World, &mut World, &HashMap<EntityId, EntityId>);
+
+/// Whether the entity's `C` differs between the two worlds, both must have it.
+pub(super) type CompareComponentsFn = fn(&World, &World, EntityId) -> bool;
+
+fn components_differ<C: PartialEq + 'static>(a: &World, b: &World, entity_id: EntityId) -> bool {
+    a.get_entity_component::<C>(entity_id).ok() != b.get_entity_component::<C>(entity_id).ok()
+}
+
+/// A list of component types, for operations that have to go over storages generically
+/// across worlds, like [`World::merge`] and [`World::diff`].
+#[derive(Default)]
+pub struct TypeRegistry {
+    move_shims: HashMap<ComponentId, MoveComponentsFn>,
+    pub(super) compare_shims: HashMap<ComponentId, CompareComponentsFn>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<C: 'static>(&mut self) -> &mut Self {
+        self.move_shims
+            .insert(ComponentId::of::<C>(), World::transfer_component_type::<C>);
+        self
+    }
+
+    /// Lets [`World::diff`] report value changes of `C`, not only additions and removals.
+    /// It's independent of [`TypeRegistry::register`].
+    pub fn register_comparable<C: PartialEq + 'static>(&mut self) -> &mut Self {
+        self.compare_shims
+            .insert(ComponentId::of::<C>(), components_differ::<C>);
+        self
+    }
+
+    pub fn contains<C: 'static>(&self) -> bool {
+        self.move_shims.contains_key(&ComponentId::of::<C>())
+    }
+}
+
+impl World {
+    /// Moves all entities of `other` into this world under fresh ids, returning the
+    /// old to new id mapping so references stored inside components can be fixed up.
+    ///
+    /// Only components whose type is in `registry` are carried over, the rest are dropped.
+    /// [`Parent`](super::Parent) and [`Children`](super::Children) are remapped on the way,
+    /// register both or neither.
+    ///
+    /// Panics if this world runs out of ids, see [`World::try_merge`].
+    pub fn merge(&mut self, other: World, registry: &TypeRegistry) -> HashMap<EntityId, EntityId> {
+        self.try_merge(other, registry)
+            .expect("Ran out of entity ids")
+    }
+
+    /// Like [`World::merge`] but returns [`Error::OutOfEntityIds`] instead of panicking, e.g.
+    /// when a [`World::with_dense_capacity`] world can't fit `other`. Nothing is merged then,
+    /// and the ids created so far are despawned again.
+    pub fn try_merge(
+        &mut self,
+        mut other: World,
+        registry: &TypeRegistry,
+    ) -> Result<HashMap<EntityId, EntityId>, Error> {
+        let mut other_entities: Vec<EntityId> = other.entity_validity_set.iter().copied().collect();
+        other_entities.sort();
+
+        let mut id_map = HashMap::with_capacity(other_entities.len());
+        for entity_id in other_entities {
+            match self.try_create_entity() {
+                Ok(new_entity_id) => {
+                    id_map.insert(entity_id, new_entity_id);
+                }
+                Err(error) => {
+                    for &new_entity_id in id_map.values() {
+                        let _ = self.despawn_entity(new_entity_id);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        // Storages are keyed by the old ids until they move, only the ids inside change here.
+        other.remap_hierarchy(&id_map);
+        for move_fn in registry.move_shims.values() {
+            move_fn(&mut other, self, &id_map);
+        }
+        self.inactive_set
+            .extend(other.inactive_set.iter().map(|entity_id| id_map[entity_id]));
+
+        Ok(id_map)
+    }
+
+    /// Moves every `C` into `dst`, under the ids `id_map` maps their entities to. The entities
+    /// themselves stay, only the components move.
+    ///
+    /// Components of entities missing from `id_map` are dropped, as are ones whose new id
+    /// isn't valid in `dst` or already has a `C` there.
+    ///
+    /// Unlike [`World::drain_components`], moving [`Parent`](super::Parent) leaves
+    /// [`Children`](super::Children) behind and the other way around, move both.
+    pub fn transfer_component_type<C: 'static>(
+        &mut self,
+        dst: &mut World,
+        id_map: &HashMap<EntityId, EntityId>,
+    ) {
+        if ComponentId::of::<C>() == ComponentId::of::<Name>() {
+            self.name_index.clear();
+        }
+        let Some(component_storage) = self.get_component_storage_mut::<C>() else {
+            return;
+        };
+
+        for (entity_id, component_data) in component_storage.drain() {
+            if let Some(&new_entity_id) = id_map.get(&entity_id) {
+                let _ = dst.add_entity_component(new_entity_id, component_data);
+            }
+        }
+    }
+}
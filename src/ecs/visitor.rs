@@ -0,0 +1,18 @@
+use super::id_types::{ComponentId, EntityId};
+use super::world::World;
+
+/// Gets every `(entity, component type)` pair in the world from [`World::accept`], for tooling
+/// that doesn't know the component types at compile time.
+pub trait ComponentVisitor {
+    fn visit(&mut self, entity_id: EntityId, component_id: ComponentId);
+}
+
+impl World {
+    /// Walks every storage, in no particular order, and each storage in its own order.
+    pub fn accept(&self, visitor: &mut impl ComponentVisitor) {
+        for (component_id, component_storage) in &self.component_storage_vecs {
+            component_storage
+                .for_each_entity(&mut |entity_id| visitor.visit(entity_id, *component_id));
+        }
+    }
+}
@@ -1,38 +1,456 @@
-use std::any::{Any, TypeId};
+use std::any::Any;
+use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::ops::ControlFlow;
+use std::sync::atomic::AtomicUsize;
 
-use super::id_types::{EntityId, ComponentId};
+use super::id_types::{EntityId, ComponentId, IdAllocator};
 use super::error::Error;
-use super::component_storage::ComponentsStorage;
+use super::component_storage::{
+    AnyComponentsStorage, ComponentStorageType, ComponentsLayout, ComponentsStorage, Storage,
+    StorageCloneFn, StorageDebugFn, debug_component,
+};
+use super::entity_ref::{EntityMut, EntityRef};
+use super::hooks::{ComponentHooks, EntityHooks};
+use super::snapshot::EntityCloneFn;
+use super::changes::ChangeKind;
+use super::name::{Name, indexed_name};
+use super::hierarchy::is_hierarchy_component;
+use super::hashing::{EcsHashMap, EcsHashSet};
 
 pub struct World {
-    component_storage_vecs: HashMap<ComponentId, Box<dyn Any>>,
+    pub(super) component_storage_vecs: EcsHashMap<ComponentId, Box<dyn AnyComponentsStorage>>,
     // component_vecs: HashMap<TypeId, Box<dyn Any>>,
     // entities: HashMap<EntityId, Entity>,
-    entity_validity_set: HashSet<EntityId>,
-    entity_counter: AtomicUsize,
+    pub(super) entity_validity_set: EcsHashSet<EntityId>,
+    /// The generation every index in `entity_validity_set` is live under, so an index can be
+    /// looked up without a scan.
+    pub(super) live_indices: EcsHashMap<usize, u32>,
+    /// Valid entities hidden with [`World::set_active`], a subset of `entity_validity_set`.
+    pub(super) inactive_set: EcsHashSet<EntityId>,
+    pub(super) entity_counter: AtomicUsize,
+    /// Storages registered with [`World::register_snapshottable`].
+    pub(super) snapshot_shims: HashMap<ComponentId, StorageCloneFn>,
+    /// Storages registered with [`World::register_clonable`].
+    pub(super) clone_shims: HashMap<ComponentId, StorageCloneFn>,
+    /// Per-entity counterparts of `clone_shims`, for [`World::clone_entity`].
+    pub(super) entity_clone_shims: HashMap<ComponentId, EntityCloneFn>,
+    /// Storages registered with [`World::register_debuggable`].
+    pub(super) debug_shims: HashMap<ComponentId, StorageDebugFn>,
+    /// Callbacks registered with [`World::on_add`] and [`World::on_remove`].
+    pub(super) component_hooks: HashMap<ComponentId, ComponentHooks>,
+    /// Callbacks registered with [`World::on_entity_created`] and
+    /// [`World::on_entity_despawned`].
+    pub(super) entity_hooks: EntityHooks,
+    /// The entities carrying each [`Name`], in the order they were given it.
+    pub(super) name_index: EcsHashMap<String, Vec<EntityId>>,
+    /// Replaces `entity_counter` when set with [`World::with_allocator`].
+    pub(super) id_allocator: Option<Box<dyn IdAllocator>>,
+    /// Set by [`World::with_dense_capacity`], no entity index may reach it.
+    pub(super) dense_capacity: Option<usize>,
+    /// The generation of the ids handed out, bumped past every one in use whenever an index
+    /// is reused, by recycling or [`World::compact_ids`], so ids keep their allocation order.
+    pub(super) entity_generation: u32,
+    /// Indices of despawned entities with the lowest generation they may come back with,
+    /// reused by [`World::create_entity`] before `entity_counter` grows. An index taken by
+    /// [`World::create_entity_with_id`] in the meantime stays in here and is skipped.
+    pub(super) free_indices: Vec<(usize, u32)>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
-        Self {
-            component_storage_vecs: HashMap::new(),
-            entity_validity_set: HashSet::new(),
+        let mut world = Self {
+            component_storage_vecs: EcsHashMap::default(),
+            entity_validity_set: EcsHashSet::default(),
+            live_indices: EcsHashMap::default(),
+            inactive_set: EcsHashSet::default(),
             entity_counter: 0.into(),
+            snapshot_shims: HashMap::new(),
+            clone_shims: HashMap::new(),
+            entity_clone_shims: HashMap::new(),
+            debug_shims: HashMap::new(),
+            component_hooks: HashMap::new(),
+            entity_hooks: EntityHooks::default(),
+            name_index: EcsHashMap::default(),
+            id_allocator: None,
+            dense_capacity: None,
+            entity_generation: 0,
+            free_indices: Vec::new(),
+        };
+        world.register_hierarchy_shims();
+        world
+    }
+
+    /// A world whose entity ids come from `id_allocator` instead of the built-in counter,
+    /// e.g. to embed a shard id in the high bits. It isn't carried over by
+    /// [`World::deep_clone`], and snapshots don't capture its state.
+    pub fn with_allocator(id_allocator: impl IdAllocator + 'static) -> Self {
+        Self {
+            id_allocator: Some(Box::new(id_allocator)),
+            ..Self::new()
         }
     }
 
+    /// Like [`World::new`] but with room for `entities` entities and `component_types`
+    /// component storages before anything has to rehash, e.g. when loading a big scene.
+    pub fn with_capacity(entities: usize, component_types: usize) -> Self {
+        Self {
+            component_storage_vecs: EcsHashMap::with_capacity_and_hasher(
+                component_types,
+                Default::default(),
+            ),
+            entity_validity_set: EcsHashSet::with_capacity_and_hasher(entities, Default::default()),
+            ..Self::new()
+        }
+    }
+
+    /// A world for a known maximum number of entities, whose storages find components through
+    /// a plain `Vec` indexed by entity id instead of a hash map, so lookups, adds and removes
+    /// never hash. Every storage allocates a slot per possible entity up front.
+    ///
+    /// Ids stay below `max_entities`: once they're all live [`World::try_create_entity`]
+    /// returns [`Error::OutOfEntityIds`], and so does [`World::create_entity_with_id`] for an
+    /// id past the cap. Despawning frees room, the index gets recycled.
+    pub fn with_dense_capacity(max_entities: usize) -> Self {
+        Self {
+            entity_validity_set: EcsHashSet::with_capacity_and_hasher(
+                max_entities,
+                Default::default(),
+            ),
+            dense_capacity: Some(max_entities),
+            ..Self::new()
+        }
+    }
+
+    /// Reuses the index of a despawned entity if there is one, under the next
+    /// [`EntityId::generation`] so handles to the old entity stay invalid.
+    ///
+    /// Panics if the world ran out of entity ids, see [`World::try_create_entity`].
     pub fn create_entity(&mut self) -> EntityId {
-        let entity_id = EntityId(self.entity_counter.fetch_add(1, Ordering::Relaxed));
+        self.try_create_entity().expect("Ran out of entity ids")
+    }
+
+    /// Like [`World::create_entity`] but returns [`Error::OutOfEntityIds`] instead of
+    /// wrapping around to ids that may still be in use.
+    ///
+    /// With a custom [`IdAllocator`] an id that's still in use is
+    /// [`Error::EntityAlreadyExists`] instead.
+    pub fn try_create_entity(&mut self) -> Result<EntityId, Error> {
+        if let Some(id_allocator) = &mut self.id_allocator {
+            let entity_id = id_allocator.next();
+            self.create_entity_with_id(entity_id)?;
+            return Ok(entity_id);
+        }
+
+        while let Some((index, generation)) = self.free_indices.pop() {
+            if self.live_indices.contains_key(&index) {
+                continue;
+            }
+
+            // A generation newer than every id handed out so far, so ids keep comparing in
+            // allocation order, see `EntityId::allocated_after`.
+            self.entity_generation = generation.max(self.entity_generation.wrapping_add(1));
+            let entity_id = EntityId::from_raw_parts(index, self.entity_generation);
+            self.insert_live_entity(entity_id);
+            self.entity_hooks.run_on_created(entity_id);
+            return Ok(entity_id);
+        }
+
+        let entity_counter = self.entity_counter.get_mut();
+        // `usize::MAX` itself is never handed out, it marks the counter as exhausted.
+        if *entity_counter == self.dense_capacity.unwrap_or(usize::MAX) {
+            return Err(Error::OutOfEntityIds);
+        }
+
+        let entity_id = EntityId::from_raw_parts(*entity_counter, self.entity_generation);
+        *entity_counter += 1;
+        self.insert_live_entity(entity_id);
+        self.entity_hooks.run_on_created(entity_id);
+        Ok(entity_id)
+    }
+
+    /// Creates the entity under an id chosen by the caller, e.g. by a server.
+    /// Later [`World::create_entity`] calls will never return it.
+    ///
+    /// The id is taken as is, generation included. An index can only be live once, so if
+    /// another entity has it under any generation that one is returned in
+    /// [`Error::EntityAlreadyExists`]. If the index was free it stops being recycled.
+    pub fn create_entity_with_id(&mut self, id: EntityId) -> Result<(), Error> {
+        if let Some(&generation) = self.live_indices.get(&id.index) {
+            return Err(Error::EntityAlreadyExists(EntityId::from_raw_parts(
+                id.index, generation,
+            )));
+        }
+        if self
+            .dense_capacity
+            .is_some_and(|dense_capacity| id.index >= dense_capacity)
+        {
+            return Err(Error::OutOfEntityIds);
+        }
+
+        self.insert_live_entity(id);
+        let entity_counter = self.entity_counter.get_mut();
+        *entity_counter = (*entity_counter).max(id.index.saturating_add(1));
+        self.entity_hooks.run_on_created(id);
+        Ok(())
+    }
+
+    /// Every path that creates an entity goes through here.
+    fn insert_live_entity(&mut self, entity_id: EntityId) {
         self.entity_validity_set.insert(entity_id);
-        entity_id
+        self.live_indices
+            .insert(entity_id.index, entity_id.generation);
+    }
+
+    /// Rebuilds `live_indices` after `entity_validity_set` was replaced as a whole.
+    pub(super) fn reindex_live_entities(&mut self) {
+        self.live_indices = self
+            .entity_validity_set
+            .iter()
+            .map(|entity_id| (entity_id.index, entity_id.generation))
+            .collect();
     }
 
     pub fn is_entity_valid(&self, id: EntityId) -> bool {
         self.entity_validity_set.contains(&id)
     }
 
+    /// [`World::is_entity_valid`] for every id, handy for sanitizing ids that came from outside.
+    /// `mask[i]` is the validity of `ids[i]`.
+    pub fn validate_entities(&self, ids: &[EntityId]) -> Vec<bool> {
+        ids.iter().map(|&id| self.is_entity_valid(id)).collect()
+    }
+
+    /// Hides the entity from iteration and queries, or shows it again, without touching its
+    /// components. It stays valid, so accessing it by id keeps working, and despawning it
+    /// still removes everything.
+    pub fn set_active(&mut self, entity_id: EntityId, active: bool) -> Result<(), Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        if active {
+            self.inactive_set.remove(&entity_id);
+        } else {
+            self.inactive_set.insert(entity_id);
+        }
+        Ok(())
+    }
+
+    /// Valid and not hidden with [`World::set_active`].
+    pub fn is_entity_active(&self, entity_id: EntityId) -> bool {
+        self.is_entity_valid(entity_id) && !self.inactive_set.contains(&entity_id)
+    }
+
+    /// A handle for reading many components of one entity, validity is only checked here.
+    pub fn entity(&self, entity_id: EntityId) -> Result<EntityRef<'_>, Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        Ok(EntityRef::new(self, entity_id))
+    }
+
+    /// Like [`World::entity`] but allows inserting components and despawning.
+    pub fn entity_mut(&mut self, entity_id: EntityId) -> Result<EntityMut<'_>, Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        Ok(EntityMut::new(self, entity_id))
+    }
+
+    /// Removes the entity along with all of its components.
+    pub fn despawn_entity(&mut self, entity_id: EntityId) -> Result<(), Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        self.remove_all_components(entity_id);
+        self.entity_validity_set.remove(&entity_id);
+        self.live_indices.remove(&entity_id.index);
+        self.inactive_set.remove(&entity_id);
+        // A custom allocator picks its own ids, recycling is up to it.
+        if self.id_allocator.is_none() {
+            self.free_indices
+                .push((entity_id.index, entity_id.generation.wrapping_add(1)));
+        }
+        self.entity_hooks.run_on_despawned(entity_id);
+
+        Ok(())
+    }
+
+    /// Same as [`World::despawn_entity`], under the name that pairs with
+    /// [`World::create_entity`].
+    pub fn destroy_entity(&mut self, entity_id: EntityId) -> Result<(), Error> {
+        self.despawn_entity(entity_id)
+    }
+
+    /// Removes every component of the entity but keeps it alive, resetting it to how
+    /// [`World::create_entity`] hands it out. It's also cut out of the hierarchy.
+    pub fn strip_entity(&mut self, entity_id: EntityId) -> Result<(), Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        self.remove_all_components(entity_id);
+        Ok(())
+    }
+
+    fn remove_all_components(&mut self, entity_id: EntityId) {
+        self.detach_from_hierarchy(entity_id);
+        if let Some(name) = self
+            .get_component_storage::<Name>()
+            .and_then(|component_storage| component_storage.get(entity_id))
+            .map(|name| name.0.clone())
+        {
+            self.unindex_name(&name, entity_id);
+        }
+
+        for (component_id, component_storage) in self.component_storage_vecs.iter_mut() {
+            if component_storage.remove_entity(entity_id)
+                && let Some(component_hooks) = self.component_hooks.get_mut(component_id)
+            {
+                component_hooks.run_on_remove(entity_id);
+            }
+        }
+    }
+
+    /// Despawns every entity that has `C`, with all of their other components,
+    /// returning how many there were.
+    /// Inactive entities are despawned too.
+    pub fn despawn_all_with<C: 'static>(&mut self) -> usize {
+        // Collected first, despawning removes from the storage being iterated.
+        let entity_ids: Vec<EntityId> = self
+            .get_component_storage::<C>()
+            .map(|component_storage| component_storage.components.entity_ids().collect())
+            .unwrap_or_default();
+        for &entity_id in &entity_ids {
+            self.despawn_entity(entity_id)
+                .expect("Only live entities have components");
+        }
+        entity_ids.len()
+    }
+
+    /// Despawns every entity `keep` returns `false` for, fully like [`World::despawn_entity`],
+    /// returning how many there were. `keep` sees the ids in ascending order, inactive
+    /// entities included.
+    pub fn retain_entities<F: FnMut(EntityId) -> bool>(&mut self, mut keep: F) -> usize {
+        let mut entity_ids: Vec<EntityId> = self.entity_validity_set.iter().copied().collect();
+        entity_ids.sort();
+        entity_ids.retain(|&entity_id| !keep(entity_id));
+
+        for &entity_id in &entity_ids {
+            self.despawn_entity(entity_id)
+                .expect("The entity was live when collected");
+        }
+        entity_ids.len()
+    }
+
+    /// [`World::despawn_all_with`] under the name of the deferred destruction idiom, where
+    /// entities are tagged during the frame and flushed at its end.
+    pub fn flush_despawns<Tag: 'static>(&mut self) -> usize {
+        self.despawn_all_with::<Tag>()
+    }
+
+    /// All live and active entities, in no particular order.
+    pub fn iter_entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.iter_entities_including_inactive()
+            .filter(|entity_id| !self.inactive_set.contains(entity_id))
+    }
+
+    /// Like [`World::iter_entities`] but also yields the ones hidden with [`World::set_active`].
+    pub fn iter_entities_including_inactive(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.entity_validity_set.iter().copied()
+    }
+
+    /// [`World::iter_entities`] in allocation order, for when determinism matters.
+    pub fn iter_entities_sorted(&self) -> impl Iterator<Item = EntityId> + use<> {
+        let mut entities: Vec<EntityId> = self.iter_entities().collect();
+        entities.sort();
+        entities.into_iter()
+    }
+
+    /// Renumbers the live entities to `0..len()`, keeping their relative order, and returns the
+    /// old to new id mapping so ids stored outside the world, or inside components other than
+    /// the hierarchy ones, can be fixed up. Every storage moves along, changes tracked for
+    /// entities that were already despawned are dropped.
+    ///
+    /// The next [`World::create_entity`] continues from `len()`, a custom [`IdAllocator`] is
+    /// left alone though. The new ids come with an [`EntityId::generation`] past every one in
+    /// use, so every old id stays invalid.
+    pub fn compact_ids(&mut self) -> HashMap<EntityId, EntityId> {
+        let mut live: Vec<EntityId> = self.entity_validity_set.iter().copied().collect();
+        live.sort();
+        // Free indices already hold a generation nobody used.
+        self.entity_generation = live
+            .iter()
+            .map(|entity_id| entity_id.generation)
+            .chain(std::iter::once(self.entity_generation))
+            .map(|generation| generation.wrapping_add(1))
+            .chain(
+                self.free_indices
+                    .drain(..)
+                    .map(|(_, generation)| generation),
+            )
+            .fold(0, u32::max);
+        let id_map: HashMap<EntityId, EntityId> = live
+            .into_iter()
+            .enumerate()
+            .map(|(index, entity_id)| {
+                (
+                    entity_id,
+                    EntityId::from_raw_parts(index, self.entity_generation),
+                )
+            })
+            .collect();
+
+        for component_storage in self.component_storage_vecs.values_mut() {
+            component_storage.remap_entities(&id_map);
+        }
+        self.remap_hierarchy(&id_map);
+
+        self.entity_validity_set = id_map.values().copied().collect();
+        self.reindex_live_entities();
+        // Stale ids can't be remapped, they're dropped rather than trusted to be absent.
+        self.inactive_set = self
+            .inactive_set
+            .iter()
+            .filter_map(|entity_id| id_map.get(entity_id).copied())
+            .collect();
+        self.name_index.retain(|_, entity_ids| {
+            entity_ids.retain_mut(|entity_id| match id_map.get(entity_id) {
+                Some(&new_entity_id) => {
+                    *entity_id = new_entity_id;
+                    true
+                }
+                None => false,
+            });
+            !entity_ids.is_empty()
+        });
+        *self.entity_counter.get_mut() = id_map.len();
+
+        id_map
+    }
+
+    /// Number of live entities, inactive ones included.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entity_validity_set.len()
+    }
+
+    /// `true` if there are no live entities, registered storages don't count.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entity_validity_set.is_empty()
+    }
+
     pub fn get_entity_component<C: 'static>(&self, entity_id: EntityId) -> Result<&C, Error> {
         if !self.is_entity_valid(entity_id) {
             return Err(Error::InvalidEntityId(entity_id));
@@ -42,12 +460,71 @@ impl World {
             .get_component_storage::<C>()
             .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
 
-        let component_index = *component_storage
-            .entity_component_map
-            .get(&entity_id)
-            .ok_or(Error::InvalidEntityComponent(std::any::type_name::<C>(), entity_id))?;
+        component_storage
+            .get(entity_id)
+            .ok_or(Error::InvalidEntityComponent(std::any::type_name::<C>(), entity_id))
+    }
+
+    /// [`World::get_entity_component`] without the validity lookup, for hot loops over ids
+    /// that are known to be live.
+    ///
+    /// # Safety
+    ///
+    /// `entity_id` must be valid in this world, i.e. created by it and not despawned since.
+    pub unsafe fn get_entity_component_unchecked<C: 'static>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<&C, Error> {
+        debug_assert!(self.is_entity_valid(entity_id));
+
+        let component_storage = self
+            .get_component_storage::<C>()
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
 
-        Ok(&component_storage.component_vec[component_index].1)
+        component_storage
+            .get(entity_id)
+            .ok_or(Error::InvalidEntityComponent(
+                std::any::type_name::<C>(),
+                entity_id,
+            ))
+    }
+
+    /// [`World::get_entity_component_mut`] without the validity lookup.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`World::get_entity_component_unchecked`].
+    pub unsafe fn get_entity_component_unchecked_mut<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<&mut C, Error> {
+        debug_assert!(self.is_entity_valid(entity_id));
+
+        let component_storage = self
+            .get_component_storage_mut::<C>()
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
+
+        component_storage
+            .get_mut(entity_id)
+            .ok_or(Error::InvalidEntityComponent(
+                std::any::type_name::<C>(),
+                entity_id,
+            ))
+    }
+
+    /// Like [`World::get_entity_component`] but a component type nobody ever added is treated
+    /// the same as the entity lacking it, `Ok(None)`. Only an invalid entity is an error.
+    pub fn get_entity_component_lenient<C: 'static>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<Option<&C>, Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        Ok(self
+            .get_component_storage::<C>()
+            .and_then(|component_storage| component_storage.get(entity_id)))
     }
 
     pub fn get_entity_component_mut<C: 'static>(
@@ -62,14 +539,24 @@ impl World {
             .get_component_storage_mut::<C>()
             .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
 
-        let component_index = *component_storage
-            .entity_component_map
-            .get(&entity_id)
-            .ok_or(Error::InvalidEntityComponent(std::any::type_name::<C>(), entity_id))?;
+        component_storage
+            .get_mut(entity_id)
+            .ok_or(Error::InvalidEntityComponent(std::any::type_name::<C>(), entity_id))
+    }
 
-        Ok(&mut component_storage.component_vec[component_index].1)
+    /// Applies `f` to the entity's `C`, `f` doesn't run at all if there's no such component.
+    pub fn map_component<C: 'static, F: FnOnce(&mut C)>(
+        &mut self,
+        entity_id: EntityId,
+        f: F,
+    ) -> Result<(), Error> {
+        f(self.get_entity_component_mut::<C>(entity_id)?);
+        Ok(())
     }
 
+    /// A failed add leaves the world untouched, the entity is validated before the storage is
+    /// registered, and the storage is only written to once nothing else can fail.
+    /// Use [`World::ensure_component_registered`] to register a type without adding it.
     pub fn add_entity_component<C: 'static>(
         &mut self,
         entity_id: EntityId,
@@ -79,34 +566,143 @@ impl World {
             return Err(Error::InvalidEntityId(entity_id));
         }
 
+        self.add_valid_entity_component(entity_id, component_data)
+    }
+
+    /// [`World::add_entity_component`] for hosts that can't afford an abort on OOM, the room
+    /// for the component is reserved first and running out of memory is
+    /// [`Error::AllocationFailed`].
+    pub fn try_add_entity_component<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+        component_data: C,
+    ) -> Result<(), Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        self.try_reserve_components::<C>(1)?;
+        self.add_valid_entity_component(entity_id, component_data)
+    }
+
+    /// Makes room for `additional` more `C`s, registering the type if it wasn't.
+    /// Fails with [`Error::AllocationFailed`] rather than aborting.
+    pub fn try_reserve_components<C: 'static>(&mut self, additional: usize) -> Result<(), Error> {
+        self.ensure_component_registered::<C>();
+        self.get_component_storage_mut::<C>()
+            .expect("Was just registered")
+            .try_reserve(additional)
+            .map_err(|_| Error::AllocationFailed(std::any::type_name::<C>()))
+    }
+
+    /// The part of [`World::add_entity_component`] after the entity was validated.
+    pub(super) fn add_valid_entity_component<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+        component_data: C,
+    ) -> Result<(), Error> {
+        self.validate_component(&component_data)?;
+        let indexed_name = indexed_name(&component_data);
+
         self.ensure_component_registered::<C>();
         let component_storage = self
             .get_component_storage_mut::<C>()
             .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
 
         // Already added?
-        if component_storage
-            .entity_component_map
-            .contains_key(&entity_id)
-        {
+        if component_storage.contains(entity_id) {
             return Err(Error::ComponentAlreadyAdded(
                 std::any::type_name::<C>(),
                 entity_id,
             ));
         }
 
-        let component_index = component_storage.component_vec.len();
+        component_storage.insert(entity_id, component_data);
 
-        component_storage
-            .component_vec
-            .push((entity_id, component_data));
-        component_storage
-            .entity_component_map
-            .insert(entity_id, component_index);
+        if let Some(name) = indexed_name {
+            self.index_name(name, entity_id);
+        }
+        if let Some(component_hooks) = self.component_hooks.get_mut(&ComponentId::of::<C>()) {
+            component_hooks.run_on_add(entity_id);
+        }
 
         Ok(())
     }
 
+    /// Adds a clone of `value` to every entity in `entity_ids`.
+    /// All of them are checked first, so on error none of them got the component.
+    pub fn add_component_to_many<C: 'static + Clone>(
+        &mut self,
+        entity_ids: &[EntityId],
+        value: C,
+    ) -> Result<(), Error> {
+        self.validate_component(&value)?;
+        let mut seen = HashSet::with_capacity(entity_ids.len());
+        for &entity_id in entity_ids {
+            if !self.is_entity_valid(entity_id) {
+                return Err(Error::InvalidEntityId(entity_id));
+            }
+            if !seen.insert(entity_id) {
+                return Err(Error::DuplicateEntityId(entity_id));
+            }
+            if self
+                .get_component_storage::<C>()
+                .is_some_and(|cs| cs.contains(entity_id))
+            {
+                return Err(Error::ComponentAlreadyAdded(
+                    std::any::type_name::<C>(),
+                    entity_id,
+                ));
+            }
+        }
+
+        self.ensure_component_registered::<C>();
+        let component_storage = self
+            .get_component_storage_mut::<C>()
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
+        component_storage.reserve(entity_ids.len());
+
+        for &entity_id in entity_ids {
+            component_storage.insert(entity_id, value.clone());
+        }
+
+        if let Some(name) = indexed_name(&value) {
+            for &entity_id in entity_ids {
+                self.index_name(name.clone(), entity_id);
+            }
+        }
+        if let Some(component_hooks) = self.component_hooks.get_mut(&ComponentId::of::<C>()) {
+            for &entity_id in entity_ids {
+                component_hooks.run_on_add(entity_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds `value` unless the entity already has a `C`, in which case `value` is dropped,
+    /// and either way returns the stored component.
+    pub fn insert_or_get_mut<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+        value: C,
+    ) -> Result<&mut C, Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        if !self
+            .get_component_storage::<C>()
+            .is_some_and(|component_storage| component_storage.contains(entity_id))
+        {
+            self.add_valid_entity_component(entity_id, value)?;
+        }
+
+        self.get_entity_component_mut(entity_id)
+    }
+
+    /// Removes the entity's component and hands it back. The last component of the type is moved
+    /// into its slot, so storage order changes, but every other entity keeps its own component.
     pub fn remove_entity_component<C: 'static>(&mut self, entity_id: EntityId) -> Result<C, Error> {
         if !self.is_entity_valid(entity_id) {
             return Err(Error::InvalidEntityId(entity_id));
@@ -116,67 +712,741 @@ impl World {
             .get_component_storage_mut::<C>()
             .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
 
-        let entity_component_index = *component_storage
-            .entity_component_map
-            .get(&entity_id)
-            .ok_or(Error::InvalidEntityComponent(std::any::type_name::<C>(), entity_id))?;
-
-        // Has a different meaning depending on whether it's the entity's component.
-        let popped_component = component_storage
-            .component_vec
-            .pop()
-            .expect("There can't be no components, because there is an entity");
-
-        let entity_component_data =
-            if entity_component_index == component_storage.component_vec.len() {
-                // The last the popped component is what we are looking for
-                popped_component.1
-            } else {
-                // We use the popped component to replace the entity's one.
-                component_storage.entity_component_map.remove(&entity_id);
-
-                // Ensure to update the entity component map to the new index
-                if let Some(index) = component_storage
-                    .entity_component_map
-                    .get_mut(&popped_component.0)
-                {
-                    *index = entity_component_index
+        let Some(component_data) = component_storage.remove(entity_id) else {
+            return Err(Error::InvalidEntityComponent(
+                std::any::type_name::<C>(),
+                entity_id,
+            ));
+        };
+
+        self.component_removed(entity_id, &component_data);
+        Ok(component_data)
+    }
+
+    /// [`World::remove_entity_component`] for when a missing component is fine,
+    /// any error is just `None`.
+    pub fn take_component<C: 'static>(&mut self, entity_id: EntityId) -> Option<C> {
+        self.remove_entity_component(entity_id).ok()
+    }
+
+    /// [`World::take_component`] named for the "remove it if it's there" idiom, never errors.
+    pub fn remove_entity_component_if_present<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Option<C> {
+        self.take_component(entity_id)
+    }
+
+    /// Overwrites a component the entity already has, returning the old value.
+    /// It's done in place, so unlike removing and adding again no indices move.
+    pub fn replace_entity_component<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+        new_component: C,
+    ) -> Result<C, Error> {
+        self.validate_component(&new_component)?;
+        let indexed_name = indexed_name(&new_component);
+
+        let component = self.get_entity_component_mut::<C>(entity_id)?;
+        let old_component = std::mem::replace(component, new_component);
+
+        self.unindex_removed_component(entity_id, &old_component);
+        if let Some(name) = indexed_name {
+            self.index_name(name, entity_id);
+        }
+
+        Ok(old_component)
+    }
+
+    /// Upsert: [`World::replace_entity_component`] if the entity already has a `C`, returning
+    /// the old one, [`World::add_entity_component`] otherwise, returning `None`.
+    pub fn set_entity_component<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+        component_data: C,
+    ) -> Result<Option<C>, Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        if self.has_component::<C>(entity_id) {
+            self.replace_entity_component(entity_id, component_data)
+                .map(Some)
+        } else {
+            self.add_valid_entity_component(entity_id, component_data)
+                .map(|()| None)
+        }
+    }
+
+    /// Like [`World::remove_entity_component`] but every other `C` component stays in its
+    /// slot, which matters to code that relies on iteration order or on slot indices.
+    ///
+    /// The price is memory rather than time, the slot is left as a tombstone and later
+    /// removals of `C`, stable or not, leave one too until [`World::compact`] reclaims them.
+    pub fn remove_entity_component_stable<C: 'static>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<C, Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        let component_storage = self
+            .get_component_storage_mut::<C>()
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
+
+        let Some(component_data) = component_storage.remove_stable(entity_id) else {
+            return Err(Error::InvalidEntityComponent(
+                std::any::type_name::<C>(),
+                entity_id,
+            ));
+        };
+
+        self.component_removed(entity_id, &component_data);
+        Ok(component_data)
+    }
+
+    /// Bookkeeping after `C` was taken off an entity, all removal paths go through here.
+    pub(super) fn component_removed<C: 'static>(&mut self, entity_id: EntityId, component: &C) {
+        self.unindex_removed_component(entity_id, component);
+        self.unlink_removed_component(entity_id, component);
+        if let Some(component_hooks) = self.component_hooks.get_mut(&ComponentId::of::<C>()) {
+            component_hooks.run_on_remove(entity_id);
+        }
+    }
+
+    /// Removes `C` from every entity, the storage stays registered and keeps its capacity.
+    /// Clearing [`Parent`](super::Parent) or [`Children`](super::Children) clears the other too, making every entity a root.
+    pub fn clear_component<C: 'static>(&mut self) {
+        self.unlink_cleared_component::<C>();
+        if let Some(component_storage) = self.get_component_storage_mut::<C>() {
+            component_storage.clear();
+        }
+
+        if ComponentId::of::<C>() == ComponentId::of::<Name>() {
+            self.name_index.clear();
+        }
+    }
+
+    /// Makes `C`'s storage keep a bitset of its entities, so joins like [`World::query2`]
+    /// between two bitset storages only visit the intersection instead of probing a map
+    /// per entity. Up to the highest entity index stored, it takes a bit plus a 4-byte
+    /// generation per index, so stale ids aren't mistaken for the entity now there.
+    pub fn enable_bitset<C: 'static>(&mut self) {
+        self.ensure_component_registered::<C>();
+        self.get_component_storage_mut::<C>()
+            .expect("Was just registered")
+            .enable_bitset();
+    }
+
+    /// Takes every `C` out of the world. The storage is empty afterwards even if the iterator
+    /// is dropped early, the rest of the components are dropped with it.
+    ///
+    /// Like [`World::clear_component`] it's a bulk operation, `on_remove` hooks don't run, and
+    /// draining [`Parent`](super::Parent) or [`Children`](super::Children) clears the other.
+    pub fn drain_components<C: 'static>(&mut self) -> impl Iterator<Item = (EntityId, C)> + '_ {
+        self.unlink_cleared_component::<C>();
+        if ComponentId::of::<C>() == ComponentId::of::<Name>() {
+            self.name_index.clear();
+        }
+
+        self.get_component_storage_mut::<C>()
+            .map(ComponentsStorage::drain)
+            .into_iter()
+            .flatten()
+    }
+
+    /// Gives back the spare capacity `C`'s storage kept around after removals, and reclaims
+    /// the tombstones left by [`World::remove_entity_component_stable`].
+    pub fn compact<C: 'static>(&mut self) {
+        if let Some(component_storage) = self.get_component_storage_mut::<C>() {
+            component_storage.compact();
+        }
+    }
+
+    /// Applies `f` to every `C` in storage order, stopping as soon as it returns
+    /// `ControlFlow::Break`, e.g. when a per-frame budget runs out. Inactive entities are
+    /// skipped.
+    pub fn map_all<C: 'static, F: FnMut(EntityId, &mut C) -> ControlFlow<()>>(&mut self, mut f: F) {
+        let inactive_set = &self.inactive_set;
+        let Some(component_storage) = self
+            .component_storage_vecs
+            .get_mut(&ComponentId::of::<C>())
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<C>>())
+        else {
+            return;
+        };
+
+        let changes = &mut component_storage.changes;
+        for (entity_id, component) in component_storage
+            .components
+            .iter_mut()
+            .filter(|(entity_id, _)| !inactive_set.contains(entity_id))
+        {
+            if let Some(changes) = changes {
+                changes.push((entity_id, ChangeKind::Modified));
+            }
+            if f(entity_id, component).is_break() {
+                break;
+            }
+        }
+    }
+
+    /// Mutable references to the `C` of several distinct entities at once.
+    pub fn get_many_mut<C: 'static, const N: usize>(
+        &mut self,
+        entity_ids: [EntityId; N],
+    ) -> Result<[&mut C; N], Error> {
+        for (i, &entity_id) in entity_ids.iter().enumerate() {
+            if !self.is_entity_valid(entity_id) {
+                return Err(Error::InvalidEntityId(entity_id));
+            }
+            if entity_ids[..i].contains(&entity_id) {
+                return Err(Error::DuplicateEntityId(entity_id));
+            }
+        }
+
+        let component_storage = self
+            .get_component_storage_mut::<C>()
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
+
+        if let Some(&entity_id) = entity_ids
+            .iter()
+            .find(|&&entity_id| !component_storage.contains(entity_id))
+        {
+            return Err(Error::InvalidEntityComponent(
+                std::any::type_name::<C>(),
+                entity_id,
+            ));
+        }
+        for entity_id in entity_ids {
+            component_storage.record_change(entity_id, ChangeKind::Modified);
+        }
+
+        let slots = component_storage.slots_mut();
+        // SAFETY: The entities were checked to be distinct.
+        Ok(entity_ids.map(|entity_id| {
+            unsafe { slots.take(entity_id) }.expect("Every entity was checked to have a component")
+        }))
+    }
+
+    /// Mutable references to two different components of the same entity.
+    ///
+    /// `A` and `B` must be different types, otherwise both references would point at the
+    /// same component. That's a debug assertion, and [`Error::DuplicateComponentType`] in release.
+    pub fn get_two_mut<A: 'static, B: 'static>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<(&mut A, &mut B), Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        let (a, b) =
+            Self::get_two_component_storages_mut::<A, B>(&mut self.component_storage_vecs)?;
+        let a = a.get_mut(entity_id).ok_or(Error::InvalidEntityComponent(
+            std::any::type_name::<A>(),
+            entity_id,
+        ))?;
+        let b = b.get_mut(entity_id).ok_or(Error::InvalidEntityComponent(
+            std::any::type_name::<B>(),
+            entity_id,
+        ))?;
+        Ok((a, b))
+    }
+
+    /// Like [`World::get_two_mut`] when only `A` is written, e.g. adding a velocity to a
+    /// position. `B` isn't reported as modified to change tracking.
+    pub fn get_mut_and_ref<A: 'static, B: 'static>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<(&mut A, &B), Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        let (a, b) =
+            Self::get_two_component_storages_mut::<A, B>(&mut self.component_storage_vecs)?;
+        let a = a.get_mut(entity_id).ok_or(Error::InvalidEntityComponent(
+            std::any::type_name::<A>(),
+            entity_id,
+        ))?;
+        let b = b.get(entity_id).ok_or(Error::InvalidEntityComponent(
+            std::any::type_name::<B>(),
+            entity_id,
+        ))?;
+        Ok((a, b))
+    }
+
+    /// Swaps the data of component `C` between entities `a` and `b`.
+    /// Only the data moves, both entities keep their slots in the storage.
+    ///
+    /// [`Parent`](super::Parent) and [`Children`](super::Children) can't be swapped, that's [`Error::HierarchyComponent`].
+    pub fn swap_components<C: 'static>(&mut self, a: EntityId, b: EntityId) -> Result<(), Error> {
+        if is_hierarchy_component(ComponentId::of::<C>()) {
+            return Err(Error::HierarchyComponent(std::any::type_name::<C>()));
+        }
+
+        for entity_id in [a, b] {
+            if !self.is_entity_valid(entity_id) {
+                return Err(Error::InvalidEntityId(entity_id));
+            }
+        }
+
+        let component_storage = self
+            .get_component_storage_mut::<C>()
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
+
+        for entity_id in [a, b] {
+            if !component_storage.contains(entity_id) {
+                return Err(Error::InvalidEntityComponent(
+                    std::any::type_name::<C>(),
+                    entity_id,
+                ));
+            }
+        }
+
+        if a != b {
+            let slots = component_storage.slots_mut();
+            // SAFETY: The entities are distinct, and both were checked to have a component.
+            let (a_component, b_component) = unsafe { (slots.take(a), slots.take(b)) };
+            std::mem::swap(
+                a_component.expect("Checked above"),
+                b_component.expect("Checked above"),
+            );
+
+            component_storage.record_change(a, ChangeKind::Modified);
+            component_storage.record_change(b, ChangeKind::Modified);
+        }
+
+        Ok(())
+    }
+
+    /// How many entities have `C`, 0 if it was never registered.
+    pub fn component_count<C: 'static>(&self) -> usize {
+        self.get_component_storage::<C>()
+            .map_or(0, |component_storage| component_storage.components.len())
+    }
+
+    /// The dense array behind `C`'s storage, e.g. for SIMD or FFI. Ids are interleaved with
+    /// the data and the order changes as components are removed.
+    ///
+    /// `None` for a [`ComponentStorageType::SoA`] storage, see [`World::component_data_slice`],
+    /// while a stable removal left tombstones, until [`World::compact`], and for zero-sized
+    /// components, which are kept as a bitset.
+    pub fn component_slice<C: 'static>(&self) -> Option<&[(EntityId, C)]> {
+        match &self.get_component_storage::<C>()?.components {
+            ComponentsLayout::Interleaved(component_vec) => Some(component_vec),
+            ComponentsLayout::SoA { .. }
+            | ComponentsLayout::Tombstoned { .. }
+            | ComponentsLayout::Tags(_) => None,
+        }
+    }
+
+    /// Like [`World::component_slice`] but mutable. Only write the components, rewriting
+    /// an id desyncs it from the storage's map, [`World::check_consistency`] would catch it.
+    pub fn component_slice_mut<C: 'static>(&mut self) -> Option<&mut [(EntityId, C)]> {
+        let component_storage = self.get_component_storage_mut::<C>()?;
+        component_storage.record_change_of_all(ChangeKind::Modified);
+        match &mut component_storage.components {
+            ComponentsLayout::Interleaved(component_vec) => Some(component_vec),
+            ComponentsLayout::SoA { .. }
+            | ComponentsLayout::Tombstoned { .. }
+            | ComponentsLayout::Tags(_) => None,
+        }
+    }
+
+    /// Just the components, contiguous, in storage order like [`World::component_slice`].
+    /// Only a [`ComponentStorageType::SoA`] storage has them apart from the ids, and not while
+    /// it has tombstones or for zero-sized components.
+    pub fn component_data_slice<C: 'static>(&self) -> Option<&[C]> {
+        match &self.get_component_storage::<C>()?.components {
+            ComponentsLayout::SoA { components, .. } => Some(components),
+            ComponentsLayout::Interleaved(_)
+            | ComponentsLayout::Tombstoned { .. }
+            | ComponentsLayout::Tags(_) => None,
+        }
+    }
+
+    /// Like [`World::component_data_slice`] but mutable.
+    pub fn component_data_slice_mut<C: 'static>(&mut self) -> Option<&mut [C]> {
+        let component_storage = self.get_component_storage_mut::<C>()?;
+        component_storage.record_change_of_all(ChangeKind::Modified);
+        match &mut component_storage.components {
+            ComponentsLayout::SoA { components, .. } => Some(components),
+            ComponentsLayout::Interleaved(_)
+            | ComponentsLayout::Tombstoned { .. }
+            | ComponentsLayout::Tags(_) => None,
+        }
+    }
+
+    /// Switches `C` to the [`ComponentStorageType::SoA`] layout, registering it if needed.
+    /// Stored components are moved over, so it can be done at any point.
+    pub fn enable_soa<C: 'static>(&mut self) {
+        self.ensure_component_registered::<C>();
+        self.get_component_storage_mut::<C>()
+            .expect("Was just registered")
+            .enable_soa();
+    }
+
+    /// `None` if `C` was never registered.
+    pub fn storage_type<C: 'static>(&self) -> Option<ComponentStorageType> {
+        Some(self.get_component_storage::<C>()?.storage_type())
+    }
+
+    /// Owned snapshot of the active entities that have `C`, in the order they were stored.
+    /// Empty if `C` was never registered.
+    pub fn entities_with<C: 'static>(&self) -> Vec<EntityId> {
+        self.get_component_storage::<C>()
+            .map(|component_storage| {
+                component_storage
+                    .components
+                    .entity_ids()
+                    .filter(|entity_id| !self.inactive_set.contains(entity_id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// [`World::entities_with`] under the name of the two-phase pattern, the supported way to
+    /// despawn or add and remove components while "iterating": snapshot the ids, let the borrow
+    /// go, then loop over them and look each one up again, skipping the ones that are gone.
+    pub fn component_ids_snapshot<C: 'static>(&self) -> Vec<EntityId> {
+        self.entities_with::<C>()
+    }
+
+    /// Live, active entities that don't have `C`, including ones with no components at all,
+    /// in no particular order.
+    pub fn entities_without<C: 'static>(&self) -> impl Iterator<Item = EntityId> + '_ {
+        let component_storage = self.get_component_storage::<C>();
+        self.iter_entities().filter(move |&entity_id| {
+            !component_storage
+                .is_some_and(|component_storage| component_storage.contains(entity_id))
+        })
+    }
+
+    /// The first active entity, in storage order, whose `C` satisfies `pred`.
+    pub fn find_entity<C: 'static, F: Fn(&C) -> bool>(&self, pred: F) -> Option<EntityId> {
+        self.get_component_storage::<C>()?
+            .components
+            .iter()
+            .find(|(entity_id, component)| {
+                !self.inactive_set.contains(entity_id) && pred(component)
+            })
+            .map(|(entity_id, _)| entity_id)
+    }
+
+    /// The component types that have a storage in this world, in no particular order.
+    pub fn registered_components(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.component_storage_vecs.keys().copied()
+    }
+
+    /// The registered component type the value is, `None` if it isn't one.
+    ///
+    /// Pass a `Box<dyn Any>` as `&*boxed`, `&boxed` is the box itself and is never a component.
+    pub fn component_type_of(&self, any: &dyn Any) -> Option<ComponentId> {
+        let component_id = ComponentId(any.type_id());
+        self.component_storage_vecs
+            .contains_key(&component_id)
+            .then_some(component_id)
+    }
+
+    /// The `std::any::type_name` of a registered component, every storage records it when
+    /// it's registered so there's no separate step for naming.
+    pub fn component_name(&self, component_id: ComponentId) -> Option<&'static str> {
+        self.component_storage_vecs
+            .get(&component_id)
+            .map(|component_storage| component_storage.component_type_name())
+    }
+
+    /// Makes [`World::debug_entity`] print the values of `C` rather than just its name.
+    pub fn register_debuggable<C: std::fmt::Debug + 'static>(&mut self) {
+        self.ensure_component_registered::<C>();
+        self.debug_shims
+            .insert(ComponentId::of::<C>(), debug_component::<C>);
+    }
+
+    /// Whether [`World::register_debuggable`] was called for `C`.
+    pub fn is_debuggable<C: 'static>(&self) -> bool {
+        self.debug_shims.contains_key(&ComponentId::of::<C>())
+    }
+
+    /// The component types the entity has, sorted by type name so equal sets compare equal.
+    pub fn entity_signature(&self, entity_id: EntityId) -> Result<Vec<ComponentId>, Error> {
+        if !self.is_entity_valid(entity_id) {
+            return Err(Error::InvalidEntityId(entity_id));
+        }
+
+        let mut components: Vec<(&str, ComponentId)> = self
+            .component_storage_vecs
+            .iter()
+            .filter(|(_, component_storage)| component_storage.contains_entity(entity_id))
+            .map(|(component_id, component_storage)| {
+                (component_storage.component_type_name(), *component_id)
+            })
+            .collect();
+        components.sort_by_key(|(name, _)| *name);
+
+        Ok(components
+            .into_iter()
+            .map(|(_, component_id)| component_id)
+            .collect())
+    }
+
+    /// How many live entities share each [`World::entity_signature`], to see how fragmented the
+    /// component combinations are. Entities without components count under the empty set.
+    pub fn component_set_histogram(&self) -> HashMap<Vec<ComponentId>, usize> {
+        let mut storages: Vec<(&ComponentId, &Box<dyn AnyComponentsStorage>)> =
+            self.component_storage_vecs.iter().collect();
+        storages.sort_by_key(|(_, component_storage)| component_storage.component_type_name());
+
+        // Storages in name order, so every signature gets built already sorted.
+        let mut signatures: HashMap<EntityId, Vec<ComponentId>> = self
+            .entity_validity_set
+            .iter()
+            .map(|&entity_id| (entity_id, Vec::new()))
+            .collect();
+        for (component_id, component_storage) in storages {
+            component_storage.for_each_entity(&mut |entity_id| {
+                if let Some(signature) = signatures.get_mut(&entity_id) {
+                    signature.push(*component_id);
                 }
+            });
+        }
+
+        let mut histogram = HashMap::new();
+        for signature in signatures.into_values() {
+            *histogram.entry(signature).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Calls `f` with each component type the entity has, in no particular order.
+    /// Like [`World::entity_signature`] without collecting, so an invalid entity just has none.
+    pub fn for_each_component_of<F: FnMut(ComponentId)>(&self, entity_id: EntityId, mut f: F) {
+        for (component_id, component_storage) in &self.component_storage_vecs {
+            if component_storage.contains_entity(entity_id) {
+                f(*component_id);
+            }
+        }
+    }
+
+    /// Something like `"Entity 3: Health(100), PlayerTag, Position"`, components are sorted
+    /// by name and only debuggable ones show their values.
+    pub fn debug_entity(&self, entity_id: EntityId) -> String {
+        if !self.is_entity_valid(entity_id) {
+            return Error::InvalidEntityId(entity_id).to_string();
+        }
 
-                std::mem::replace(
-                    &mut component_storage.component_vec[entity_component_index],
-                    popped_component,
+        let mut components: Vec<(&str, Option<String>)> = self
+            .component_storage_vecs
+            .iter()
+            .filter(|(_, component_storage)| component_storage.contains_entity(entity_id))
+            .map(|(component_id, component_storage)| {
+                let value = self
+                    .debug_shims
+                    .get(component_id)
+                    .and_then(|debug_fn| debug_fn(component_storage.as_ref(), entity_id));
+                (
+                    short_type_name(component_storage.component_type_name()),
+                    value,
                 )
-                .1
-            };
+            })
+            .collect();
+        components.sort();
 
-        component_storage.entity_component_map.remove(&entity_id);
-        Ok(entity_component_data)
+        let components: Vec<String> = components
+            .into_iter()
+            .map(|(name, value)| value.unwrap_or_else(|| name.to_string()))
+            .collect();
+        format!("Entity {}: {}", entity_id, components.join(", "))
+    }
+
+    /// Estimated bytes used by each storage, summing it gives a rough footprint of the world.
+    pub fn memory_report(&self) -> HashMap<ComponentId, usize> {
+        self.component_storage_vecs
+            .iter()
+            .map(|(component_id, component_storage)| {
+                (*component_id, component_storage.memory_usage())
+            })
+            .collect()
+    }
+
+    /// Verifies that every storage's map and vec agree with each other and only hold live
+    /// entities. It's `O(n)` over all components, meant for tests and debugging.
+    pub fn check_consistency(&self) -> Result<(), String> {
+        if let Some(entity_id) = self
+            .inactive_set
+            .difference(&self.entity_validity_set)
+            .next()
+        {
+            return Err(format!("Entity {} is inactive but invalid", entity_id));
+        }
+        if let Some(entity_id) = self.entity_validity_set.iter().find(|entity_id| {
+            self.live_indices.get(&entity_id.index) != Some(&entity_id.generation)
+        }) {
+            return Err(format!("Entity {} is valid but its index isn't live", entity_id));
+        }
+        if self.live_indices.len() != self.entity_validity_set.len() {
+            return Err(format!(
+                "{} indices are live for {} valid entities",
+                self.live_indices.len(),
+                self.entity_validity_set.len()
+            ));
+        }
+
+        self.component_storage_vecs
+            .values()
+            .try_for_each(|component_storage| {
+                component_storage.check_consistency(&self.entity_validity_set)
+            })
+    }
+
+    /// Panics with the broken invariant in debug builds, does nothing in release.
+    pub fn debug_assert_consistent(&self) {
+        if cfg!(debug_assertions)
+            && let Err(error) = self.check_consistency()
+        {
+            panic!("{}", error);
+        }
+    }
+
+    /// `true` if `C` was ever registered in this world, regardless of any entity having it.
+    pub fn contains_component_type<C: 'static>(&self) -> bool {
+        self.component_storage_vecs
+            .contains_key(&ComponentId::of::<C>())
+    }
+
+    /// Whether the entity has a `C`, without borrowing it. `false` for invalid entities and
+    /// types that were never registered, inactive entities still count.
+    pub fn has_component<C: 'static>(&self, entity_id: EntityId) -> bool {
+        self.is_entity_valid(entity_id)
+            && self
+                .get_component_storage::<C>()
+                .is_some_and(|component_storage| component_storage.contains(entity_id))
+    }
+
+    /// [`World::has_component`] for every type at once, e.g. ids from
+    /// [`World::registered_components`].
+    pub fn has_components(&self, entity_id: EntityId, component_ids: &[ComponentId]) -> bool {
+        self.is_entity_valid(entity_id)
+            && component_ids.iter().all(|component_id| {
+                self.component_storage_vecs
+                    .get(component_id)
+                    .is_some_and(|component_storage| component_storage.contains_entity(entity_id))
+            })
     }
 
     /// Returns `true` if the component was already registered.
     /// Otherwise will register the component.
     pub fn ensure_component_registered<C: 'static>(&mut self) -> bool {
         let component_id = ComponentId::of::<C>();
-        if self.component_storage_vecs.contains_key(&component_id) {
-            true
-        } else {
-            self.component_storage_vecs
-                .insert(component_id, Box::new(ComponentsStorage::<C>::new()));
+        if let Entry::Vacant(entry) = self.component_storage_vecs.entry(component_id) {
+            entry.insert(Box::new(ComponentsStorage::<C>::with_max_entities(
+                self.dense_capacity,
+            )));
             false
+        } else {
+            true
         }
     }
 
-    fn get_component_storage<C: 'static>(&self) -> Option<&ComponentsStorage<C>> {
+    pub(super) fn get_component_storage<C: 'static>(&self) -> Option<&ComponentsStorage<C>> {
         self.component_storage_vecs
             .get(&ComponentId::of::<C>())
-            .and_then(|cs| (*cs).downcast_ref::<ComponentsStorage<C>>())
+            .and_then(|cs| (cs.as_ref() as &dyn Any).downcast_ref::<ComponentsStorage<C>>())
     }
 
-    fn get_component_storage_mut<C: 'static>(&mut self) -> Option<&mut ComponentsStorage<C>> {
+    pub(super) fn get_component_storage_mut<C: 'static>(
+        &mut self,
+    ) -> Option<&mut ComponentsStorage<C>> {
         self.component_storage_vecs
             .get_mut(&ComponentId::of::<C>())
-            .and_then(|cs| (*cs).downcast_mut::<ComponentsStorage<C>>())
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<C>>())
+    }
+
+    /// Both storages at once, which is only possible for two different types.
+    pub(super) fn get_two_component_storages_mut<A: 'static, B: 'static>(
+        component_storage_vecs: &mut ComponentStorages,
+    ) -> Result<(&mut ComponentsStorage<A>, &mut ComponentsStorage<B>), Error> {
+        let [a, b] = Self::get_disjoint_component_storages_mut(
+            component_storage_vecs,
+            [
+                (ComponentId::of::<A>(), std::any::type_name::<A>()),
+                (ComponentId::of::<B>(), std::any::type_name::<B>()),
+            ],
+        )?;
+
+        let a = a
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<A>>())
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<A>()))?;
+        let b = b
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<B>>())
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<B>()))?;
+        Ok((a, b))
+    }
+
+    /// Like [`World::get_two_component_storages_mut`] for three types.
+    pub(super) fn get_three_component_storages_mut<A: 'static, B: 'static, C: 'static>(
+        component_storage_vecs: &mut ComponentStorages,
+    ) -> Result<ThreeStoragesMut<'_, A, B, C>, Error> {
+        let [a, b, c] = Self::get_disjoint_component_storages_mut(
+            component_storage_vecs,
+            [
+                (ComponentId::of::<A>(), std::any::type_name::<A>()),
+                (ComponentId::of::<B>(), std::any::type_name::<B>()),
+                (ComponentId::of::<C>(), std::any::type_name::<C>()),
+            ],
+        )?;
+
+        let a = a
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<A>>())
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<A>()))?;
+        let b = b
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<B>>())
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<B>()))?;
+        let c = c
+            .and_then(|cs| (cs.as_mut() as &mut dyn Any).downcast_mut::<ComponentsStorage<C>>())
+            .ok_or(Error::InvalidWorldComponent(std::any::type_name::<C>()))?;
+        Ok((a, b, c))
+    }
+
+    /// The type-erased storages of distinct component types. Passing a type twice would alias
+    /// the same storage mutably, that trips a debug assertion and is an error in release.
+    fn get_disjoint_component_storages_mut<'s, const N: usize>(
+        component_storage_vecs: &'s mut ComponentStorages,
+        components: [(ComponentId, &'static str); N],
+    ) -> Result<[Option<&'s mut Box<dyn AnyComponentsStorage>>; N], Error> {
+        for (i, &(component_id, name)) in components.iter().enumerate() {
+            if components[..i]
+                .iter()
+                .any(|(other_id, _)| *other_id == component_id)
+            {
+                debug_assert!(
+                    false,
+                    "Component {} was requested mutably more than once, the references would alias",
+                    name
+                );
+                return Err(Error::DuplicateComponentType(name));
+            }
+        }
+
+        Ok(component_storage_vecs
+            .get_disjoint_mut(components.each_ref().map(|(component_id, _)| component_id)))
     }
 }
 
+type ComponentStorages = EcsHashMap<ComponentId, Box<dyn AnyComponentsStorage>>;
+
+type ThreeStoragesMut<'w, A, B, C> = (
+    &'w mut ComponentsStorage<A>,
+    &'w mut ComponentsStorage<B>,
+    &'w mut ComponentsStorage<C>,
+);
+
+/// `a::b::Position<c::Vec3>` becomes `Position<c::Vec3>`, generics are left alone.
+fn short_type_name(type_name: &str) -> &str {
+    let path_end = type_name.find('<').unwrap_or(type_name.len());
+    let name_start = type_name[..path_end].rfind("::").map_or(0, |i| i + 2);
+    &type_name[name_start..]
+}
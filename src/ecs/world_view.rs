@@ -0,0 +1,78 @@
+use super::error::Error;
+use super::id_types::EntityId;
+use super::world::World;
+
+/// A read-only borrow of the world that can be shared between threads, from
+/// [`World::view`]. Every component accessor requires the component to be `Sync`.
+#[derive(Copy, Clone)]
+pub struct WorldView<'w> {
+    world: &'w World,
+}
+
+// SAFETY: A view only ever reads the world's own bookkeeping, which is plain data, and the
+// storages of components that are `Sync`, as every accessor below requires. Storages of other
+// components, the hooks and the id allocator are never touched through it, and the borrow
+// rules out a writer.
+unsafe impl Send for WorldView<'_> {}
+unsafe impl Sync for WorldView<'_> {}
+
+impl World {
+    /// Many views can exist at once, e.g. one per thread running read-only queries.
+    pub fn view(&self) -> WorldView<'_> {
+        WorldView { world: self }
+    }
+}
+
+impl<'w> WorldView<'w> {
+    pub fn is_entity_valid(&self, entity_id: EntityId) -> bool {
+        self.world.is_entity_valid(entity_id)
+    }
+
+    /// See [`World::iter_entities`].
+    pub fn iter_entities(&self) -> impl Iterator<Item = EntityId> + 'w {
+        self.world.iter_entities()
+    }
+
+    pub fn len(&self) -> usize {
+        self.world.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.world.is_empty()
+    }
+
+    pub fn get_entity_component<C: Sync + 'static>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<&'w C, Error> {
+        self.world.get_entity_component(entity_id)
+    }
+
+    /// See [`World::get_entity_component_lenient`].
+    pub fn get_entity_component_lenient<C: Sync + 'static>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<Option<&'w C>, Error> {
+        self.world.get_entity_component_lenient(entity_id)
+    }
+
+    /// See [`World::query2`].
+    pub fn query2<A: Sync + 'static, B: Sync + 'static>(
+        &self,
+    ) -> impl Iterator<Item = (EntityId, &'w A, &'w B)> + 'w {
+        self.world.query2()
+    }
+
+    pub fn count_query2<A: Sync + 'static, B: Sync + 'static>(&self) -> usize {
+        self.world.count_query2::<A, B>()
+    }
+
+    pub fn entities_with<C: Sync + 'static>(&self) -> Vec<EntityId> {
+        self.world.entities_with::<C>()
+    }
+
+    /// See [`World::find_entity`].
+    pub fn find_entity<C: Sync + 'static, F: Fn(&C) -> bool>(&self, pred: F) -> Option<EntityId> {
+        self.world.find_entity(pred)
+    }
+}
@@ -1,3 +1,5 @@
+// The ECS is consumed as a library, the binary itself doesn't use most of it yet.
+#[allow(dead_code, unused_imports)]
 mod ecs;
 
 fn main() {}